@@ -1,11 +1,7 @@
 //! Read a 24x16 EEPROM.
-#![allow(clippy::unusual_byte_groupings)]
 
+use buspirate_hal::eeprom::{Eeprom, Part};
 use buspirate_hal::{Configuration, PsuConfig};
-use embedded_hal::i2c::I2c;
-
-const PAGES: usize = 8;
-const PAGE_SIZE: usize = 256;
 
 fn main() -> anyhow::Result<()> {
     env_logger::builder().format_timestamp_millis().init();
@@ -24,15 +20,8 @@ fn main() -> anyhow::Result<()> {
         .psu(psu_config)
         .pullup(true)
         .build();
-    let mut bp = buspirate_hal::open(&path)?.enter_i2c_mode(400_000, false, Some(extra_config))?;
-    let mut buf = [0u8; PAGE_SIZE * PAGES];
-    let address: u8 = 0b1010_000;
-
-    for page in 0..PAGES {
-        let start = page * PAGE_SIZE;
-        let end = start + PAGE_SIZE;
-        bp.write_read(address + (page as u8), &[0], &mut buf[start..end])?;
-    }
+    let bp = buspirate_hal::open(&path)?.enter_i2c_mode(400_000, false, Some(extra_config))?;
+    let buf = Eeprom::new(bp, Part::C16).read_all()?;
     println!("{buf:?}");
     Ok(())
 }
@@ -1,12 +1,11 @@
 
+use std::io::Read;
+
 use buspirate_hal::{
     open, ChipSelectPolarity, ClockPhase, ClockPolarity, Configuration, PsuConfig,
 };
-use embedded_hal::spi::SpiDevice;
 
 const N_BYTES: usize = 1024 * 1024 * 32 / 8;
-const PAGE_SIZE: usize = 512;
-const N_PAGES: usize = N_BYTES / PAGE_SIZE;
 
 // const READ_CHIP_INFO: u8 = 0x9F;
 const READ: u8 = 0x03;
@@ -34,12 +33,8 @@ fn main() {
         .unwrap();
 
     let mut dump = vec![0u8; N_BYTES];
-    for page_idx in 0..N_PAGES {
-        let start_idx = page_idx * PAGE_SIZE;
-        let end_idx = start_idx + PAGE_SIZE;
-        let page_buf = &mut dump[start_idx..end_idx];
-
-        let [_, a2, a1, a0] = (start_idx as u32).to_be_bytes();
-        bp.transfer(page_buf, &[READ, a2, a1, a0]).unwrap();
-    }
+    bp.read_stream(&[READ, 0, 0, 0], N_BYTES)
+        .unwrap()
+        .read_exact(&mut dump)
+        .unwrap();
 }
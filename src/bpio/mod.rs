@@ -1,36 +1,170 @@
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
 
 use bit_field::BitField;
 use bpio2 as generated;
 use flatbuffers::FlatBufferBuilder;
 use log::{debug, trace};
 
+use crate::buspirate::POLL_INTERVAL;
 use crate::modes::Modes;
-use crate::{EncodedRequest, Error, Response};
+use crate::{EncodedRequest, Error, Response, Transport};
 
 // BPIO interface major version
-const VERSION_MAJOR: u8 = 2;
+pub(crate) const VERSION_MAJOR: u8 = 2;
 // BPIO interface minor version
 const MINIMUM_VERSION_MINOR: u16 = 0;
 
-fn send(mut port: impl Read + Write, req: EncodedRequest) -> Result<Response, Error> {
-    port.write_all(&req.cobs_encoded)?;
+// Maximum response packet size assumed until the firmware reports one.
+pub(crate) const DEFAULT_MAX_PACKET_SIZE: usize = 1024;
+// Room left for the flatbuffer framing around the data in a packet.
+pub(crate) const PACKET_OVERHEAD: usize = 64;
+
+/// Bounds on waiting for and receiving a response packet.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResponseLimits {
+    /// Overall time allowed for a complete response to arrive.
+    pub(crate) timeout: Duration,
+    /// Largest decoded response packet that is accepted.
+    pub(crate) max_packet_size: usize,
+}
+
+impl ResponseLimits {
+    pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Largest COBS-encoded frame, including the sentinel, for the packet size.
+    fn max_frame_size(&self) -> usize {
+        // COBS adds at most one byte per 254 bytes of data.
+        self.max_packet_size + self.max_packet_size.div_ceil(254) + 1
+    }
+}
+
+impl Default for ResponseLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Self::DEFAULT_TIMEOUT,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+        }
+    }
+}
+
+/// A transport to the Bus Pirate, with any bytes received beyond the end of the
+/// last response frame.
+pub(crate) struct Connection {
+    transport: Box<dyn Transport>,
+    /// Bytes received that are not yet part of a complete response frame.
+    pending: Vec<u8>,
+    /// Set when a response did not arrive in time. It may still arrive, and
+    /// would then be taken for the response to the next request.
+    stale: bool,
+}
+
+impl Connection {
+    pub(crate) fn new(transport: Box<dyn Transport>) -> Self {
+        Self {
+            transport,
+            pending: Vec::new(),
+            stale: false,
+        }
+    }
+
+    /// Take the first complete frame received, including its sentinel.
+    fn take_frame(&mut self) -> Option<Vec<u8>> {
+        let end = self.pending.iter().position(|&b| b == 0)?;
+        Some(self.pending.drain(..=end).collect())
+    }
+
+    /// Discard everything received so far, and anything that arrives before
+    /// the transport next has nothing to read.
+    ///
+    /// This catches a late response that has at least started arriving by the
+    /// time the next request is sent.
+    fn drain(&mut self) -> Result<(), Error> {
+        let mut discarded = std::mem::take(&mut self.pending).len();
+        let mut read_buf = [0u8; 256];
+        loop {
+            match self.transport.read(&mut read_buf) {
+                Ok(0) => break,
+                Ok(n) => discarded += n,
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                    break;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        debug!("Discarded {discarded} bytes received after a timeout");
+        self.stale = false;
+        Ok(())
+    }
+}
+
+fn send(
+    port: &mut Connection,
+    limits: ResponseLimits,
+    req: EncodedRequest,
+) -> Result<Response, Error> {
+    if port.stale {
+        port.drain()?;
+    }
+    port.transport.write_all(&req.cobs_encoded)?;
     trace!("Send: wrote {}", req.cobs_encoded.len());
 
-    // 1kB decode buffer
-    let mut decoded_bytes = [0u8; 1024];
-    let mut decoder = cobs::CobsDecoder::new(&mut decoded_bytes);
+    let deadline = Instant::now() + limits.timeout;
     let mut read_buf = [0u8; 256];
 
-    // TODO: This should be bounded.
     loop {
-        let bytes_read = port.read(&mut read_buf)?;
+        while let Some(frame) = port.take_frame() {
+            if frame.len() == 1 {
+                // A lone sentinel is an empty frame, so keep waiting for a response.
+                continue;
+            }
+            if frame.len() > limits.max_frame_size() {
+                return Err(Error::FrameTooLarge {
+                    limit: limits.max_packet_size,
+                });
+            }
+            let Some(response) = Response::decode(&frame)? else {
+                return Err(Error::TruncatedFrame {
+                    received: frame.len(),
+                });
+            };
+            trace!("Send: {}-byte response packet", response.cobs_decoded.len());
+            return Ok(response);
+        }
+        if port.pending.len() > limits.max_frame_size() {
+            // The rest of the frame is still to come, so resynchronise before
+            // the next request.
+            port.stale = true;
+            return Err(Error::FrameTooLarge {
+                limit: limits.max_packet_size,
+            });
+        }
+
+        if Instant::now() >= deadline {
+            port.stale = true;
+            return Err(Error::Timeout);
+        }
+        let bytes_read = match port.transport.read(&mut read_buf) {
+            Ok(0) => {
+                return Err(Error::TruncatedFrame {
+                    received: port.pending.len(),
+                });
+            }
+            Ok(n) => n,
+            // The read already waited for data, so retry at once.
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                continue;
+            }
+            // Nothing has arrived yet, so wait rather than spin.
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
         trace!("Send: read {bytes_read}");
-        if let Some(report) = decoder.push(&read_buf[..bytes_read])? {
-            let packet = decoded_bytes[..report.frame_size()].to_vec();
-            trace!("Send: {}-byte response packet", packet.len());
-            return Ok(Response::new(packet));
-        }
+        port.pending.extend_from_slice(&read_buf[..bytes_read]);
     }
 }
 
@@ -51,11 +185,11 @@ impl From<generated::ResponsePacketContents> for Error {
 macro_rules! check_response {
     ($packet:ident, $e:expr) => {{
         if let Some(msg) = $packet.error() {
-            Err(Error::BpioErrorMessage(msg.to_owned()))
+            Err(Error::from_bpio_message(msg))
         } else if let Some(v) = $e {
             if let Some(error_message) = v.error() {
                 // Correct response type, but contains an error message.
-                Err(Error::BpioErrorMessage(error_message.to_owned()))
+                Err(Error::from_bpio_message(error_message))
             } else {
                 // Correct response type, no error.
                 Ok(v)
@@ -66,11 +200,18 @@ macro_rules! check_response {
     }};
 }
 
+// Declared after `check_response!` so the macro is in scope for submodules.
+mod status;
+
+pub(crate) use status::send_status_request;
+pub use status::{BpioVersion, Status, StatusQuery};
+
 pub(crate) fn send_data_request(
-    port: impl Read + Write,
+    port: &mut Connection,
+    limits: ResponseLimits,
     req: EncodedRequest,
 ) -> Result<Option<Vec<u8>>, Error> {
-    let response = send(port, req)?;
+    let response = send(port, limits, req)?;
     let packet = generated::root_as_response_packet(&response.cobs_decoded)?;
     let data_response = check_response!(packet, packet.contents_as_data_response())?;
     Ok(data_response
@@ -79,6 +220,43 @@ pub(crate) fn send_data_request(
         .map(|v| v.iter().collect()))
 }
 
+/// Check that a data response holds exactly the `expected` number of bytes.
+///
+/// Responses to requests that read nothing carry no data at all.
+pub(crate) fn check_received(received: Option<Vec<u8>>, expected: usize) -> Result<Vec<u8>, Error> {
+    let data = check_received_up_to(received, expected)?;
+    if data.len() < expected {
+        return Err(Error::ShortRead {
+            expected,
+            got: data.len(),
+        });
+    }
+    Ok(data)
+}
+
+/// Check that a data response holds no more than `limit` bytes, for reads
+/// where the amount available is not known in advance.
+pub(crate) fn check_received_up_to(
+    received: Option<Vec<u8>>,
+    limit: usize,
+) -> Result<Vec<u8>, Error> {
+    let data = received.unwrap_or_default();
+    if data.len() > limit {
+        return Err(Error::ExcessData {
+            limit,
+            got: data.len(),
+        });
+    }
+    Ok(data)
+}
+
+/// Copy received data into `buf`, which it must fill exactly.
+pub(crate) fn copy_received(received: Option<Vec<u8>>, buf: &mut [u8]) -> Result<(), Error> {
+    let data = check_received(received, buf.len())?;
+    buf.copy_from_slice(&data);
+    Ok(())
+}
+
 #[derive(Debug, bon::Builder)]
 pub(crate) struct I2cRequest<'a> {
     start: bool,
@@ -153,6 +331,7 @@ pub(crate) struct DataRequest<'a> {
     start: bool,
     start_alt: Option<bool>,
     stop: bool,
+    stop_alt: Option<bool>,
     bytes_to_write: Option<&'a [u8]>,
     #[builder(with = |n: usize| n as u16)]
     bytes_to_read: Option<u16>,
@@ -171,6 +350,9 @@ impl<'a> From<DataRequest<'a>> for EncodedRequest {
             data_request.add_start_alt(start_alt);
         }
         data_request.add_stop_main(request.stop);
+        if let Some(stop_alt) = request.stop_alt {
+            data_request.add_stop_alt(stop_alt);
+        }
         if let Some(bytes_read) = request.bytes_to_read {
             data_request.add_bytes_read(bytes_read);
         }
@@ -279,7 +461,11 @@ impl PsuConfig {
     }
 }
 
-#[derive(Debug)]
+/// Changes to the direction and level of the IO pins.
+///
+/// Only pins that have been explicitly set are changed; the rest are left as
+/// they are.
+#[derive(Debug, Default)]
 pub struct IoConfig {
     direction_mask: u8,
     direction: u8,
@@ -288,6 +474,10 @@ pub struct IoConfig {
 }
 
 impl IoConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn set_direction(&mut self, pin: usize, direction: IoDirection) {
         assert!(matches!(pin, 0..8), "Pin must be in range 0..8");
         self.direction_mask.set_bit(pin, true);
@@ -482,29 +672,32 @@ impl ModeConfiguration {
 }
 
 pub(crate) fn send_configuration_request(
-    port: impl Read + Write,
+    port: &mut Connection,
+    limits: ResponseLimits,
     config: Configuration,
 ) -> Result<(), Error> {
     debug!("Sending config request");
     trace!("{config:?}");
-    send_full_configuration_request(port, config, None)
+    send_full_configuration_request(port, limits, config, None)
 }
 
 // TODO: Method to change configuration of current mode, without changing the mode itself(?)
 
 fn send_full_configuration_request(
-    port: impl Read + Write,
+    port: &mut Connection,
+    limits: ResponseLimits,
     config: Configuration,
     mode: Option<(Modes, ModeConfiguration)>,
 ) -> Result<(), Error> {
     let full_config = FullConfiguration { config, mode };
-    let response_bytes = send(port, full_config.into())?;
+    let response_bytes = send(port, limits, full_config.into())?;
     let packet = generated::root_as_response_packet(&response_bytes.cobs_decoded)?;
     check_response!(packet, packet.contents_as_configuration_response()).map(drop)
 }
 
 pub(crate) fn change_mode(
-    port: impl Read + Write,
+    port: &mut Connection,
+    limits: ResponseLimits,
     mode: Modes,
     mode_config: ModeConfiguration,
     extra_config: Option<Configuration<'_>>,
@@ -513,5 +706,130 @@ pub(crate) fn change_mode(
     debug!("Changing mode to {mode}");
     trace!("{mode_config:#?}");
     trace!("{config:#?}");
-    send_full_configuration_request(port, config, Some((mode, mode_config)))
+    send_full_configuration_request(port, limits, config, Some((mode, mode_config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Script {
+        /// Chunks returned by successive reads.
+        readable: VecDeque<Vec<u8>>,
+        /// Chunks made readable by successive writes, one per request.
+        replies: VecDeque<Vec<u8>>,
+    }
+
+    /// A transport whose reads and replies are set up by the test.
+    #[derive(Clone, Default)]
+    struct Scripted(Arc<Mutex<Script>>);
+
+    impl Scripted {
+        fn readable(&self, chunk: Vec<u8>) {
+            self.0.lock().unwrap().readable.push_back(chunk);
+        }
+
+        fn reply(&self, chunk: Vec<u8>) {
+            self.0.lock().unwrap().replies.push_back(chunk);
+        }
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(chunk) = self.0.lock().unwrap().readable.pop_front() else {
+                return Err(ErrorKind::WouldBlock.into());
+            };
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut script = self.0.lock().unwrap();
+            if let Some(reply) = script.replies.pop_front() {
+                script.readable.push_back(reply);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(packet: &[u8]) -> Vec<u8> {
+        EncodedRequest::encode(packet).cobs_encoded
+    }
+
+    fn request() -> EncodedRequest {
+        EncodedRequest::encode(&[0x01, 0x02])
+    }
+
+    #[test]
+    fn frames_sharing_a_read_are_kept() {
+        let script = Scripted::default();
+        let mut port = Connection::new(Box::new(script.clone()));
+        let limits = ResponseLimits::default();
+
+        let mut both = frame(&[0x10, 0x00, 0x11]);
+        both.extend(frame(&[0x20]));
+        let split = both.len() - 2;
+        script.reply(both[..split].to_vec());
+        script.reply(both[split..].to_vec());
+
+        let first = send(&mut port, limits, request()).unwrap();
+        assert_eq!(first.cobs_decoded, [0x10, 0x00, 0x11]);
+        let second = send(&mut port, limits, request()).unwrap();
+        assert_eq!(second.cobs_decoded, [0x20]);
+    }
+
+    #[test]
+    fn late_response_is_discarded() {
+        let script = Scripted::default();
+        let mut port = Connection::new(Box::new(script.clone()));
+        let limits = ResponseLimits {
+            timeout: Duration::from_millis(10),
+            ..ResponseLimits::default()
+        };
+
+        assert!(matches!(
+            send(&mut port, limits, request()),
+            Err(Error::Timeout)
+        ));
+
+        script.readable(frame(&[0x10]));
+        script.reply(frame(&[0x20]));
+        let response = send(&mut port, limits, request()).unwrap();
+        assert_eq!(response.cobs_decoded, [0x20]);
+    }
+
+    #[test]
+    fn received_length_is_checked() {
+        assert_eq!(check_received(Some(vec![1, 2]), 2).unwrap(), [1, 2]);
+        assert!(check_received(None, 0).unwrap().is_empty());
+        assert!(matches!(
+            check_received(Some(vec![1]), 2),
+            Err(Error::ShortRead {
+                expected: 2,
+                got: 1
+            })
+        ));
+        assert!(matches!(
+            check_received(Some(vec![1, 2, 3]), 2),
+            Err(Error::ExcessData { limit: 2, got: 3 })
+        ));
+
+        assert_eq!(check_received_up_to(Some(vec![1]), 2).unwrap(), [1]);
+        assert!(check_received_up_to(None, 2).unwrap().is_empty());
+        assert!(matches!(
+            check_received_up_to(Some(vec![1, 2, 3]), 2),
+            Err(Error::ExcessData { limit: 2, got: 3 })
+        ));
+    }
 }
@@ -0,0 +1,222 @@
+use bpio2 as generated;
+use flatbuffers::FlatBufferBuilder;
+use log::{debug, trace};
+
+use super::{BitOrder, Connection, MINIMUM_VERSION_MINOR, ResponseLimits, VERSION_MAJOR, send};
+use crate::{EncodedRequest, Error};
+
+/// Sections of the Bus Pirate status that can be requested.
+///
+/// Fields belonging to sections that were not requested are left at their
+/// default (zero, false or empty) values in the returned [`Status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusQuery {
+    All,
+    Version,
+    Mode,
+    Pullup,
+    Psu,
+    Adc,
+    Io,
+    Disk,
+    Led,
+}
+
+impl StatusQuery {
+    fn for_bpio(self) -> generated::StatusRequestTypes {
+        match self {
+            StatusQuery::All => generated::StatusRequestTypes::All,
+            StatusQuery::Version => generated::StatusRequestTypes::Version,
+            StatusQuery::Mode => generated::StatusRequestTypes::Mode,
+            StatusQuery::Pullup => generated::StatusRequestTypes::Pullup,
+            StatusQuery::Psu => generated::StatusRequestTypes::PSU,
+            StatusQuery::Adc => generated::StatusRequestTypes::ADC,
+            StatusQuery::Io => generated::StatusRequestTypes::IO,
+            StatusQuery::Disk => generated::StatusRequestTypes::Disk,
+            StatusQuery::Led => generated::StatusRequestTypes::LED,
+        }
+    }
+}
+
+/// Decoded contents of a BPIO `StatusResponse`.
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    pub hardware_version_major: u8,
+    pub hardware_version_minor: u8,
+    pub firmware_version_major: u8,
+    pub firmware_version_minor: u16,
+    pub firmware_git_hash: Option<String>,
+    pub firmware_date: Option<String>,
+    /// Major version of the BPIO flatbuffer interface.
+    pub bpio_version_major: u8,
+    /// Minor version of the BPIO flatbuffer interface.
+    pub bpio_version_minor: u16,
+    pub modes_available: Vec<String>,
+    pub mode_current: Option<String>,
+    pub mode_pin_labels: Vec<String>,
+    pub mode_bit_order: Option<BitOrder>,
+    pub mode_max_packet_size: u32,
+    pub mode_max_write: u32,
+    pub mode_max_read: u32,
+    pub psu_enabled: bool,
+    pub psu_set_mv: u32,
+    pub psu_set_ma: u32,
+    pub psu_measured_mv: u32,
+    pub psu_measured_ma: u32,
+    pub psu_current_error: bool,
+    pub pullup_enabled: bool,
+    /// ADC readings in millivolts, one per IO pin.
+    pub adc_mv: Vec<u32>,
+    /// IO pin directions as a bitmask, where a set bit is an output.
+    pub io_direction: u8,
+    /// IO pin levels as a bitmask, where a set bit is high.
+    pub io_value: u8,
+    pub disk_size_mb: f32,
+    pub disk_used_mb: f32,
+    pub led_count: u8,
+}
+
+impl Status {
+    fn from_response(response: generated::StatusResponse<'_>) -> Self {
+        fn strings(
+            v: Option<flatbuffers::Vector<'_, flatbuffers::ForwardsUOffset<&str>>>,
+        ) -> Vec<String> {
+            v.map(|v| v.iter().map(str::to_owned).collect())
+                .unwrap_or_default()
+        }
+
+        let mode_current = response.mode_current().map(str::to_owned);
+        // Bit order is only meaningful if mode information was returned.
+        let mode_bit_order = mode_current.as_ref().map(|_| {
+            if response.mode_bitorder_msb() {
+                BitOrder::Msb
+            } else {
+                BitOrder::Lsb
+            }
+        });
+
+        Self {
+            hardware_version_major: response.hardware_version_major(),
+            hardware_version_minor: response.hardware_version_minor(),
+            firmware_version_major: response.firmware_version_major(),
+            firmware_version_minor: response.firmware_version_minor(),
+            firmware_git_hash: response.firmware_git_hash().map(str::to_owned),
+            firmware_date: response.firmware_date().map(str::to_owned),
+            bpio_version_major: response.version_flatbuffers_major(),
+            bpio_version_minor: response.version_flatbuffers_minor(),
+            modes_available: strings(response.modes_available()),
+            mode_current,
+            mode_pin_labels: strings(response.mode_pin_labels()),
+            mode_bit_order,
+            mode_max_packet_size: response.mode_max_packet_size(),
+            mode_max_write: response.mode_max_write(),
+            mode_max_read: response.mode_max_read(),
+            psu_enabled: response.psu_enabled(),
+            psu_set_mv: response.psu_set_mv(),
+            psu_set_ma: response.psu_set_ma(),
+            psu_measured_mv: response.psu_measured_mv(),
+            psu_measured_ma: response.psu_measured_ma(),
+            psu_current_error: response.psu_current_error(),
+            pullup_enabled: response.pullup_enabled(),
+            adc_mv: response
+                .adc_mv()
+                .map(|v| v.iter().collect())
+                .unwrap_or_default(),
+            io_direction: response.io_direction(),
+            io_value: response.io_value(),
+            disk_size_mb: response.disk_size_mb(),
+            disk_used_mb: response.disk_used_mb(),
+            led_count: response.led_count(),
+        }
+    }
+}
+
+/// Version of the BPIO2 flatbuffer interface.
+///
+/// Versions compare by major then minor version, so features added in a minor
+/// version can be gated with, for example, `version >= BpioVersion::new(2, 1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BpioVersion {
+    pub major: u8,
+    pub minor: u16,
+}
+
+impl BpioVersion {
+    /// The version this crate implements.
+    pub(crate) const SUPPORTED: Self = Self::new(VERSION_MAJOR, MINIMUM_VERSION_MINOR);
+
+    pub const fn new(major: u8, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Check that the version reported by the Bus Pirate is compatible.
+    ///
+    /// The major version must match, and the minor version must be at least the
+    /// minimum this crate relies on.
+    pub(crate) fn from_status(status: &Status) -> Result<Self, Error> {
+        let found = Self::new(status.bpio_version_major, status.bpio_version_minor);
+        if found.major != Self::SUPPORTED.major || found.minor < Self::SUPPORTED.minor {
+            return Err(Error::IncompatibleVersion {
+                found,
+                minimum: Self::SUPPORTED,
+            });
+        }
+        Ok(found)
+    }
+}
+
+impl std::fmt::Display for BpioVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+struct StatusRequest<'a> {
+    queries: &'a [StatusQuery],
+}
+
+impl<'a> From<StatusRequest<'a>> for EncodedRequest {
+    fn from(request: StatusRequest<'a>) -> Self {
+        let mut fbb = FlatBufferBuilder::with_capacity(64);
+
+        let queries: Vec<generated::StatusRequestTypes> =
+            request.queries.iter().map(|q| q.for_bpio()).collect();
+        let query = fbb.create_vector(&queries);
+
+        let mut status_request = generated::StatusRequestBuilder::new(&mut fbb);
+        status_request.add_query(query);
+        let status_request = status_request.finish();
+
+        let mut packet = generated::RequestPacketBuilder::new(&mut fbb);
+        packet.add_version_major(VERSION_MAJOR);
+        packet.add_minimum_version_minor(MINIMUM_VERSION_MINOR);
+        packet.add_contents_type(generated::RequestPacketContents::StatusRequest);
+        packet.add_contents(status_request.as_union_value());
+        let packet = packet.finish();
+        fbb.finish_minimal(packet);
+        EncodedRequest::encode(fbb.finished_data())
+    }
+}
+
+/// Request the status of the Bus Pirate.
+///
+/// An empty `queries` slice is equivalent to [`StatusQuery::All`].
+pub(crate) fn send_status_request(
+    port: &mut Connection,
+    limits: ResponseLimits,
+    queries: &[StatusQuery],
+) -> Result<Status, Error> {
+    let queries = if queries.is_empty() {
+        &[StatusQuery::All][..]
+    } else {
+        queries
+    };
+    debug!("Sending status request");
+    trace!("{queries:?}");
+    let response = send(port, limits, StatusRequest { queries }.into())?;
+    let packet = generated::root_as_response_packet(&response.cobs_decoded)?;
+    let status_response = check_response!(packet, packet.contents_as_status_response())?;
+    let status = Status::from_response(status_response);
+    trace!("{status:#?}");
+    Ok(status)
+}
@@ -1,57 +1,101 @@
-use std::{marker::PhantomData, time::Duration};
+use std::time::Duration;
 
 use log::debug;
-use serialport::SerialPort;
 
-use crate::bpio;
-use crate::modes::{ActiveMode, I2c, Modes, Spi};
-use crate::util::{ChipSelectPolarity, ClockPhase, ClockPolarity};
-use crate::{Configuration, EncodedRequest, Error, ModeConfiguration};
+use crate::bpio::{self, Connection, ResponseLimits};
+use crate::infrared::CarrierFrequency;
+use crate::jtag::{JtagPins, TapState};
+use crate::modes::{
+    ActiveMode, Dio, HdUart, HiZ, I2c, Infrared, Jtag, Led, Modes, OneWire, Spi, ThreeWire,
+    TwoWire, Uart,
+};
+use crate::util::{
+    ChipSelectPolarity, ClockPhase, ClockPolarity, HdUartConfig, TransferLimits, UartConfig,
+    max_packet_size,
+};
+use crate::{
+    BpioVersion, Configuration, EncodedRequest, Error, LedType, ModeConfiguration, Status,
+    StatusQuery, Transport,
+};
+
+/// Delay between polls of the Bus Pirate's receive buffer while waiting for data.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(1);
 
 /// HAL wrapper
 pub struct BusPirate<M: ActiveMode> {
-    _mode: PhantomData<M>,
-    serial_port: Box<dyn SerialPort>,
+    /// Mode marker, which also holds any host-side state for the mode.
+    pub(crate) mode: M,
+    transport: Connection,
+    /// Transfer size limits for the current mode.
+    pub(crate) limits: TransferLimits,
+    /// Bounds on waiting for and receiving responses.
+    response_limits: ResponseLimits,
+    /// BPIO2 interface version reported by the Bus Pirate.
+    version: BpioVersion,
 }
 
-/// Consume $this and return it with the new mode type.
+/// Consume $this and return it with the new mode.
 macro_rules! with_mode {
-    ($this:ident, $mode:ty) => {{
-        let Self { _mode, serial_port } = $this;
-        BusPirate::<$mode> {
-            _mode: PhantomData,
-            serial_port,
+    ($this:ident, $mode:expr) => {{
+        let Self {
+            mode: _,
+            transport,
+            limits,
+            response_limits,
+            version,
+        } = $this;
+        BusPirate {
+            mode: $mode,
+            transport,
+            limits,
+            response_limits,
+            version,
         }
     }};
 }
 
-pub fn open(address: &str) -> Result<BusPirate<I2c>, Error> {
-    let mut serial_port = serialport::new(address, 115_200)
-        // TODO: choose a sensible timeout value.
-        .timeout(Duration::from_secs(1))
+/// Open a connection to the Bus Pirate's BPIO2 serial port.
+///
+/// Use [`discover`](crate::discover) to find the port of an attached Bus Pirate.
+///
+/// The Bus Pirate is put into high-impedance mode, so opening the port does not
+/// drive any pins.
+pub fn open(address: &str) -> Result<BusPirate<HiZ>, Error> {
+    let serial_port = serialport::new(address, 115_200)
+        // Each read only waits briefly; the overall response deadline is
+        // enforced separately.
+        .timeout(Duration::from_millis(100))
         .open()?;
     debug!("Connected to serial port {address:?}");
+    BusPirate::from_transport(serial_port)
+}
 
-    // Put the Bus Pirate into high-impedance mode upon opening the serial port.
-    // bpio::change_mode(
-    //     &mut serial_port,
-    //     Modes::HiZ,
-    //     ModeConfiguration::empty(),
-    //     None,
-    // )?;
-
-    // TODO: This is temporary while HiZ mode is unsupported.
-    bpio::change_mode(
-        &mut serial_port,
-        Modes::I2c,
-        ModeConfiguration::empty(),
-        None,
-    )?;
-
-    Ok(BusPirate::<I2c> {
-        _mode: PhantomData,
-        serial_port,
-    })
+impl BusPirate<HiZ> {
+    /// Connect to a Bus Pirate's BPIO2 interface over any transport.
+    ///
+    /// As with [`open`], the Bus Pirate's BPIO2 version is checked and it is put
+    /// into high-impedance mode.
+    pub fn from_transport(transport: impl Transport + 'static) -> Result<Self, Error> {
+        let mut bp = BusPirate {
+            mode: HiZ,
+            transport: Connection::new(Box::new(transport)),
+            limits: TransferLimits::default(),
+            response_limits: ResponseLimits::default(),
+            version: BpioVersion::SUPPORTED,
+        };
+        // Check the version before sending anything that might be misunderstood.
+        // The transfer limits are read at the same time: the firmware's BPIO2
+        // buffers are the same size in every mode, so they only need reading once.
+        let status = bp.status(&[StatusQuery::Version, StatusQuery::Mode])?;
+        bp.version = BpioVersion::from_status(&status)?;
+        debug!("BPIO2 version {}", bp.version);
+        bp.limits = TransferLimits::from_status(&status);
+        bp.response_limits.max_packet_size = max_packet_size(&status);
+        debug!("Transfer limits: {:?}", bp.limits);
+        // Put the Bus Pirate into high-impedance mode upon connecting.
+        bp.set_mode(Modes::HiZ, ModeConfiguration::empty(), None)?;
+        Ok(bp)
+    }
 }
 
 impl<M: ActiveMode> BusPirate<M> {
@@ -59,11 +103,54 @@ impl<M: ActiveMode> BusPirate<M> {
         &mut self,
         request: impl Into<EncodedRequest>,
     ) -> Result<Option<Vec<u8>>, Error> {
-        bpio::send_data_request(&mut self.serial_port, request.into())
+        bpio::send_data_request(&mut self.transport, self.response_limits, request.into())
+    }
+
+    /// Read the bytes the Bus Pirate has received so far, up to `buf.len()` or
+    /// the largest read the firmware allows.
+    ///
+    /// Returns the number of bytes copied into `buf`, which may be zero. This is
+    /// used by the asynchronous serial modes, where the amount of data waiting
+    /// is not known in advance.
+    pub(crate) fn read_available(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = buf.len().min(self.limits.max_read);
+        let request = bpio::DataRequest::builder()
+            .start(false)
+            .stop(false)
+            .bytes_to_read(len)
+            .build();
+
+        let received = self.send_data_request(request)?;
+        let data = bpio::check_received_up_to(received, len)?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    /// Write bytes without any start or stop conditions.
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let request = bpio::DataRequest::builder()
+            .start(false)
+            .stop(false)
+            .bytes_to_write(bytes)
+            .build();
+        self.send_data_request(request).map(drop)
+    }
+
+    /// The BPIO2 interface version reported by the Bus Pirate.
+    pub fn version(&self) -> BpioVersion {
+        self.version
+    }
+
+    /// Set how long to wait for the Bus Pirate to respond to each request.
+    ///
+    /// Requests that receive no complete response in this time fail with
+    /// [`Error::Timeout`]. The default is two seconds.
+    pub fn set_response_timeout(&mut self, timeout: Duration) {
+        self.response_limits.timeout = timeout;
     }
 
     pub fn configure(&mut self, request: Configuration) -> Result<(), Error> {
-        bpio::send_configuration_request(&mut self.serial_port, request)
+        bpio::send_configuration_request(&mut self.transport, self.response_limits, request)
     }
 
     fn set_mode(
@@ -72,7 +159,22 @@ impl<M: ActiveMode> BusPirate<M> {
         mode_config: ModeConfiguration,
         extra_config: Option<Configuration>,
     ) -> Result<(), Error> {
-        bpio::change_mode(&mut self.serial_port, mode, mode_config, extra_config)
+        bpio::change_mode(
+            &mut self.transport,
+            self.response_limits,
+            mode,
+            mode_config,
+            extra_config,
+        )
+    }
+
+    /// Put the Bus Pirate into high-impedance mode.
+    ///
+    /// All pins are released, which makes this the safe state in which to leave
+    /// the Bus Pirate between uses.
+    pub fn into_hiz(mut self) -> Result<BusPirate<HiZ>, Error> {
+        self.set_mode(Modes::HiZ, ModeConfiguration::empty(), None)?;
+        Ok(with_mode!(self, HiZ))
     }
 
     /// Put the Bus Pirate into I2C mode.
@@ -113,6 +215,142 @@ impl<M: ActiveMode> BusPirate<M> {
         Ok(with_mode!(self, Spi))
     }
 
+    /// Put the Bus Pirate into UART mode.
+    pub fn enter_uart_mode(
+        mut self,
+        config: UartConfig,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<Uart>, Error> {
+        self.set_mode(Modes::Uart, config.mode_configuration(), extra_config)?;
+        Ok(with_mode!(self, Uart))
+    }
+
+    /// Put the Bus Pirate into half-duplex UART mode.
+    pub fn enter_hduart_mode(
+        mut self,
+        config: HdUartConfig,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<HdUart>, Error> {
+        self.set_mode(Modes::HdUart, config.mode_configuration(), extra_config)?;
+        Ok(with_mode!(
+            self,
+            HdUart {
+                turnaround: config.turnaround,
+                timeout: config.timeout,
+            }
+        ))
+    }
+
+    /// Put the Bus Pirate into 1-Wire mode.
+    pub fn enter_onewire_mode(
+        mut self,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<OneWire>, Error> {
+        self.set_mode(Modes::OneWire, ModeConfiguration::empty(), extra_config)?;
+        Ok(with_mode!(self, OneWire))
+    }
+
+    /// Put the Bus Pirate into generic 2-wire mode.
+    pub fn enter_two_wire_mode(
+        mut self,
+        speed: u32,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<TwoWire>, Error> {
+        let mode_config = ModeConfiguration::builder().speed(speed).build();
+        self.set_mode(Modes::TwoWire, mode_config, extra_config)?;
+        Ok(with_mode!(self, TwoWire))
+    }
+
+    /// Put the Bus Pirate into generic 3-wire mode.
+    pub fn enter_three_wire_mode(
+        mut self,
+        speed: u32,
+        chip_select_polarity: ChipSelectPolarity,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<ThreeWire>, Error> {
+        let mode_config = ModeConfiguration::builder()
+            .speed(speed)
+            .chip_select_idle(chip_select_polarity.for_bpio())
+            .build();
+        self.set_mode(Modes::ThreeWire, mode_config, extra_config)?;
+        Ok(with_mode!(self, ThreeWire))
+    }
+
+    /// Put the Bus Pirate into digital IO mode.
+    pub fn enter_dio_mode(
+        mut self,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<Dio>, Error> {
+        self.set_mode(Modes::Dio, ModeConfiguration::empty(), extra_config)?;
+        Ok(with_mode!(self, Dio { driven: 0 }))
+    }
+
+    /// Put the Bus Pirate into LED mode, to drive a string of the given LED type.
+    pub fn enter_led_mode(
+        mut self,
+        led_type: LedType,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<Led>, Error> {
+        let mode_config = ModeConfiguration::builder()
+            .submode(led_type.for_bpio())
+            .build();
+        self.set_mode(Modes::Led, mode_config, extra_config)?;
+        Ok(with_mode!(
+            self,
+            Led {
+                led_type,
+                brightness: 31,
+            }
+        ))
+    }
+
+    /// Put the Bus Pirate into infrared mode.
+    ///
+    /// Transmissions are modulated at `carrier`. The receiver is left at the
+    /// firmware's default.
+    pub fn enter_infrared_mode(
+        mut self,
+        carrier: CarrierFrequency,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<Infrared>, Error> {
+        let mode_config = ModeConfiguration::builder()
+            .tx_modulation(carrier.hz())
+            .build();
+        self.set_mode(Modes::Infrared, mode_config, extra_config)?;
+        Ok(with_mode!(self, Infrared))
+    }
+
+    /// Put the Bus Pirate into DIO mode and bit-bang JTAG on the given pins.
+    ///
+    /// The TAP controller is reset, leaving it in Test-Logic-Reset. Fails with
+    /// [`Error::InvalidPin`] if a signal is on a pin outside `0..8` or shares
+    /// a pin with another.
+    pub fn enter_jtag_mode(
+        mut self,
+        pins: JtagPins,
+        extra_config: Option<Configuration>,
+    ) -> Result<BusPirate<Jtag>, Error> {
+        pins.validate()?;
+        // The firmware's JTAG mode doesn't expose shifting, so use DIO instead.
+        self.set_mode(Modes::Dio, ModeConfiguration::empty(), extra_config)?;
+        let mut bp = with_mode!(
+            self,
+            Jtag {
+                pins,
+                state: TapState::TestLogicReset,
+            }
+        );
+        bp.jtag_init()?;
+        Ok(bp)
+    }
+
+    /// Query the Bus Pirate's status.
+    ///
+    /// Pass an empty slice (or [`StatusQuery::All`]) to request everything.
+    pub fn status(&mut self, queries: &[StatusQuery]) -> Result<Status, Error> {
+        bpio::send_status_request(&mut self.transport, self.response_limits, queries)
+    }
+
     pub fn selftest(&mut self) -> Result<(), Error> {
         let config_request = Configuration::builder().hardware_selftest(true).build();
         self.configure(config_request)
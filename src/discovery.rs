@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use log::debug;
+use serialport::SerialPortType;
+
+use crate::{BusPirate, Error, modes::HiZ};
+
+/// USB vendor ID of the Bus Pirate 5 and later.
+const USB_VID: u16 = 0x1209;
+/// USB product ID of the Bus Pirate 5 and later.
+const USB_PID: u16 = 0x7331;
+/// USB interface number of the BPIO2 serial port. The first serial port, with
+/// interfaces 0 and 1, is the terminal.
+const BPIO_INTERFACE: u8 = 2;
+
+/// The BPIO2 serial port of an attached Bus Pirate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusPirateInfo {
+    /// Path or name of the serial port, as passed to [`open`](crate::open).
+    pub port: String,
+    pub serial_number: Option<String>,
+    /// USB product string, as reported by the operating system.
+    pub product: Option<String>,
+}
+
+impl BusPirateInfo {
+    /// Open the Bus Pirate's BPIO2 serial port.
+    pub fn open(&self) -> Result<BusPirate<HiZ>, Error> {
+        crate::open(&self.port)
+    }
+}
+
+/// Sort key for port names that orders any trailing number numerically, so that
+/// `ttyACM9` comes before `ttyACM10`.
+fn port_order(name: &str) -> (&str, Option<u64>) {
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    (prefix, name[prefix.len()..].parse().ok())
+}
+
+/// Find the BPIO2 serial ports of all attached Bus Pirates.
+///
+/// Each Bus Pirate has two USB serial ports: a terminal and the BPIO2 binary
+/// interface. Only the BPIO2 port is returned. If the platform doesn't report
+/// USB interface numbers, the second port of each Bus Pirate (by name) is
+/// assumed to be the BPIO2 port.
+pub fn discover() -> Result<Vec<BusPirateInfo>, Error> {
+    let mut ports: Vec<_> = serialport::available_ports()?
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb) if usb.vid == USB_VID && usb.pid == USB_PID => {
+                Some((port.port_name, usb))
+            }
+            _ => None,
+        })
+        .collect();
+    ports.sort_by(|(a, _), (b, _)| port_order(a).cmp(&port_order(b)));
+
+    // Ports seen so far for each serial number, for when interface numbers
+    // aren't available.
+    let mut seen: HashMap<Option<String>, usize> = HashMap::new();
+    let mut found = Vec::new();
+    for (port, usb) in ports {
+        let index = seen.entry(usb.serial_number.clone()).or_default();
+        let is_bpio = match usb.interface {
+            Some(interface) => interface == BPIO_INTERFACE,
+            None => *index == 1,
+        };
+        *index += 1;
+
+        debug!(
+            "Found Bus Pirate port {port:?}, interface {:?}",
+            usb.interface
+        );
+        if is_bpio {
+            found.push(BusPirateInfo {
+                port,
+                serial_number: usb.serial_number,
+                product: usb.product,
+            });
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_sort_by_trailing_number() {
+        let mut names = [
+            "/dev/ttyACM10",
+            "/dev/ttyACM9",
+            "COM3",
+            "/dev/ttyACM1",
+            "COM12",
+            "/dev/ttyUSB0",
+        ];
+        names.sort_by_key(|name| port_order(*name));
+        assert_eq!(
+            names,
+            [
+                "/dev/ttyACM1",
+                "/dev/ttyACM9",
+                "/dev/ttyACM10",
+                "/dev/ttyUSB0",
+                "COM3",
+                "COM12",
+            ]
+        );
+    }
+}
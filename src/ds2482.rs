@@ -0,0 +1,157 @@
+//! 1-Wire through a DS2482-100 I2C bridge.
+//!
+//! BPIO2 1-Wire requests only transfer whole bytes, so the Bus Pirate's own
+//! 1-Wire mode cannot search for devices. The DS2482 generates single-bit time
+//! slots itself, so [`Ds2482`] implements [`OneWireSearch`] over any [`I2c`]
+//! bus that reports this crate's [`Error`], such as
+//! [`BusPirate<I2c>`](crate::BusPirate).
+
+use std::time::{Duration, Instant};
+
+use embedded_hal::i2c::I2c;
+use log::debug;
+
+use crate::{
+    Error,
+    buspirate::POLL_INTERVAL,
+    onewire::{OneWireBus, OneWireSearch},
+};
+
+/// 7-bit address of the DS2482-100, with the AD1 and AD0 pins tied low.
+const BASE_ADDRESS: u8 = 0x18;
+
+const DEVICE_RESET: u8 = 0xF0;
+const SET_READ_POINTER: u8 = 0xE1;
+const ONE_WIRE_RESET: u8 = 0xB4;
+const ONE_WIRE_SINGLE_BIT: u8 = 0x87;
+const ONE_WIRE_WRITE_BYTE: u8 = 0xA5;
+const ONE_WIRE_READ_BYTE: u8 = 0x96;
+
+/// Read pointer code for the read data register.
+const READ_DATA_REGISTER: u8 = 0xE1;
+
+/// 1-Wire busy.
+const STATUS_BUSY: u8 = 1 << 0;
+/// Presence pulse detected.
+const STATUS_PRESENCE: u8 = 1 << 1;
+/// Short detected.
+const STATUS_SHORT: u8 = 1 << 2;
+/// Device reset has occurred.
+const STATUS_RESET: u8 = 1 << 4;
+/// Single bit result.
+const STATUS_BIT_RESULT: u8 = 1 << 5;
+
+/// Generous upper bound on a 1-Wire reset or byte, which take about 1.2ms and
+/// 0.6ms at standard speed.
+const ONE_WIRE_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// A DS2482-100 single-channel I2C to 1-Wire bridge.
+pub struct Ds2482<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c<Error = Error>> Ds2482<I> {
+    /// A bridge at the usual address, with the AD1 and AD0 pins tied low.
+    pub fn new(i2c: I) -> Self {
+        Self {
+            i2c,
+            address: BASE_ADDRESS,
+        }
+    }
+
+    /// Set the 7-bit address, for bridges with AD1 or AD0 tied high.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Give back the I2C bus.
+    pub fn release(self) -> I {
+        self.i2c
+    }
+
+    /// Reset the bridge, ending any 1-Wire activity.
+    ///
+    /// Fails with [`Error::NoDevice`] if the bridge does not report the reset.
+    pub fn device_reset(&mut self) -> Result<(), Error> {
+        debug!("DS2482: device reset");
+        self.i2c.write(self.address, &[DEVICE_RESET])?;
+        if self.wait_while_busy()? & STATUS_RESET == 0 {
+            return Err(Error::NoDevice);
+        }
+        Ok(())
+    }
+
+    /// Send a 1-Wire command, and return the status once it has finished.
+    fn one_wire_command(&mut self, command: &[u8]) -> Result<u8, Error> {
+        self.i2c.write(self.address, command)?;
+        self.wait_while_busy()
+    }
+
+    /// Poll the status register, which commands leave selected for reading,
+    /// until the 1-Wire operation finishes.
+    fn wait_while_busy(&mut self) -> Result<u8, Error> {
+        let deadline = Instant::now() + ONE_WIRE_TIMEOUT;
+        loop {
+            let mut status = [0u8];
+            self.i2c.read(self.address, &mut status)?;
+            if status[0] & STATUS_BUSY == 0 {
+                return Ok(status[0]);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::BusyTimeout {
+                    timeout: ONE_WIRE_TIMEOUT,
+                });
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl<I: I2c<Error = Error>> OneWireBus for Ds2482<I> {
+    fn reset(&mut self) -> Result<bool, Error> {
+        debug!("DS2482: 1-Wire reset");
+        let status = self.one_wire_command(&[ONE_WIRE_RESET])?;
+        if status & STATUS_SHORT != 0 {
+            return Err(Error::BusError);
+        }
+        Ok(status & STATUS_PRESENCE != 0)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        debug!("DS2482: 1-Wire write w:{}", bytes.len());
+        for &byte in bytes {
+            self.one_wire_command(&[ONE_WIRE_WRITE_BYTE, byte])?;
+        }
+        Ok(())
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        debug!("DS2482: 1-Wire read r:{}", buf.len());
+        for byte in buf {
+            self.one_wire_command(&[ONE_WIRE_READ_BYTE])?;
+            let mut data = [0u8];
+            self.i2c.write_read(
+                self.address,
+                &[SET_READ_POINTER, READ_DATA_REGISTER],
+                &mut data,
+            )?;
+            *byte = data[0];
+        }
+        Ok(())
+    }
+}
+
+impl<I: I2c<Error = Error>> OneWireSearch for Ds2482<I> {
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error> {
+        self.one_wire_command(&[ONE_WIRE_SINGLE_BIT, if bit { 0x80 } else { 0x00 }])
+            .map(drop)
+    }
+
+    fn read_bit(&mut self) -> Result<bool, Error> {
+        // A read slot is a write slot of a one, sampled afterwards.
+        let status = self.one_wire_command(&[ONE_WIRE_SINGLE_BIT, 0x80])?;
+        Ok(status & STATUS_BIT_RESULT != 0)
+    }
+}
@@ -0,0 +1,273 @@
+//! Reading and writing 24xx-family I2C EEPROMs.
+//!
+//! [`Eeprom`] works over any [`I2c`] bus that reports this crate's [`Error`],
+//! such as [`BusPirate<I2c>`](crate::BusPirate). Reads and writes are split to
+//! suit the part, so whole images can be read, written and verified in one
+//! call.
+
+use std::time::{Duration, Instant};
+
+use embedded_hal::i2c::I2c;
+use log::debug;
+
+use crate::{Error, buspirate::POLL_INTERVAL};
+
+/// Base 7-bit address of 24xx EEPROMs, with the A2..A0 pins tied low.
+const BASE_ADDRESS: u8 = 0x50;
+
+/// Generous upper bound on the write cycle time, which is at most 5ms or 10ms
+/// on most parts.
+const WRITE_CYCLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The size and page size of a 24xx EEPROM.
+///
+/// Parts up to 2KiB take a one-byte memory address and larger parts a
+/// two-byte address. Memory beyond that range is selected by bits of the I2C
+/// address, usually the low bits, so the part answers to several consecutive
+/// addresses. Parts that use other bits, such as the 24xx1025, need
+/// [`with_block_select_shift`](Part::with_block_select_shift).
+///
+/// Page sizes vary between manufacturers, so check the datasheet and use
+/// [`Part::new`] if the common size given here is wrong for a part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    size: usize,
+    page_size: usize,
+    block_select_shift: u32,
+}
+
+impl Part {
+    /// 24xx01: 128 bytes in 8-byte pages.
+    pub const C01: Self = Self::sized(128, 8);
+    /// 24xx02: 256 bytes in 8-byte pages.
+    pub const C02: Self = Self::sized(256, 8);
+    /// 24xx04: 512 bytes in 16-byte pages.
+    pub const C04: Self = Self::sized(512, 16);
+    /// 24xx08: 1KiB in 16-byte pages.
+    pub const C08: Self = Self::sized(1024, 16);
+    /// 24xx16: 2KiB in 16-byte pages.
+    pub const C16: Self = Self::sized(2048, 16);
+    /// 24xx32: 4KiB in 32-byte pages.
+    pub const C32: Self = Self::sized(4 * 1024, 32);
+    /// 24xx64: 8KiB in 32-byte pages.
+    pub const C64: Self = Self::sized(8 * 1024, 32);
+    /// 24xx128: 16KiB in 64-byte pages.
+    pub const C128: Self = Self::sized(16 * 1024, 64);
+    /// 24xx256: 32KiB in 64-byte pages.
+    pub const C256: Self = Self::sized(32 * 1024, 64);
+    /// 24xx512: 64KiB in 128-byte pages.
+    pub const C512: Self = Self::sized(64 * 1024, 128);
+    /// 24xxM01: 128KiB in 256-byte pages.
+    pub const CM01: Self = Self::sized(128 * 1024, 256);
+    /// 24xxM02: 256KiB in 256-byte pages.
+    pub const CM02: Self = Self::sized(256 * 1024, 256);
+    /// 24xx1025: 128KiB in 128-byte pages, with the block select bit in place
+    /// of A2.
+    pub const C1025: Self = Self::sized(128 * 1024, 128).with_block_select_shift(2);
+
+    const fn sized(size: usize, page_size: usize) -> Self {
+        Self {
+            size,
+            page_size,
+            block_select_shift: 0,
+        }
+    }
+
+    /// A part of `size` bytes with `page_size`-byte pages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero, `page_size` does not divide it, or it needs
+    /// more than the three block select bits of the I2C address.
+    pub fn new(size: usize, page_size: usize) -> Self {
+        assert!(size > 0, "EEPROM size must not be zero");
+        assert!(
+            page_size > 0 && size.is_multiple_of(page_size),
+            "Page size must divide the EEPROM size"
+        );
+        let part = Self::sized(size, page_size);
+        assert!(part.blocks() <= 8, "EEPROM is too large to address");
+        part
+    }
+
+    /// Select blocks of memory with the I2C address bits from bit `shift` up,
+    /// rather than from bit 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block select bits would not fit in A2..A0.
+    pub const fn with_block_select_shift(mut self, shift: u32) -> Self {
+        assert!(
+            shift < 3 && (self.blocks() - 1) << shift < 8,
+            "Block select bits must fit in A2..A0"
+        );
+        self.block_select_shift = shift;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// The lowest I2C address bit used to select blocks of memory.
+    pub fn block_select_shift(&self) -> u32 {
+        self.block_select_shift
+    }
+
+    /// Number of memory address bytes sent after the I2C address.
+    pub const fn address_bytes(&self) -> usize {
+        if self.size > 2048 { 2 } else { 1 }
+    }
+
+    /// Memory selected by the address bytes alone.
+    const fn block_size(&self) -> usize {
+        1 << (8 * self.address_bytes())
+    }
+
+    /// Number of blocks of memory, each at its own I2C address.
+    const fn blocks(&self) -> usize {
+        self.size.div_ceil(self.block_size())
+    }
+}
+
+/// A 24xx-family I2C EEPROM.
+pub struct Eeprom<I> {
+    i2c: I,
+    part: Part,
+    address: u8,
+}
+
+impl<I: I2c<Error = Error>> Eeprom<I> {
+    /// An EEPROM at the usual address, with the A2..A0 pins tied low.
+    pub fn new(i2c: I, part: Part) -> Self {
+        Self {
+            i2c,
+            part,
+            address: BASE_ADDRESS,
+        }
+    }
+
+    /// Set the 7-bit address, for parts with any of the A2..A0 pins tied high.
+    ///
+    /// Parts that use address bits to select blocks of memory ignore the
+    /// matching pins, so give the address of the first block.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn part(&self) -> Part {
+        self.part
+    }
+
+    /// Give back the I2C bus.
+    pub fn release(self) -> I {
+        self.i2c
+    }
+
+    /// Read from `offset` into `buf`.
+    pub fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_range(offset, buf.len())?;
+        debug!("EEPROM: read {} bytes at {offset:#X}", buf.len());
+        // Sequential reads may not continue into the next block.
+        let block_size = self.part.block_size();
+        let mut done = 0;
+        while done < buf.len() {
+            let address = offset + done;
+            let len = (block_size - address % block_size).min(buf.len() - done);
+            let (device, memory_address) = self.locate(address);
+            self.i2c
+                .write_read(device, &memory_address, &mut buf[done..done + len])?;
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Read the whole part.
+    pub fn read_all(&mut self) -> Result<Vec<u8>, Error> {
+        let mut contents = vec![0u8; self.part.size];
+        self.read(0, &mut contents)?;
+        Ok(contents)
+    }
+
+    /// Write `data` at `offset` a page at a time, then verify it.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        self.check_range(offset, data.len())?;
+        debug!("EEPROM: write {} bytes at {offset:#X}", data.len());
+        let page_size = self.part.page_size;
+        let mut done = 0;
+        while done < data.len() {
+            let address = offset + done;
+            // Writes must not cross a page boundary, or they wrap around.
+            let len = (page_size - address % page_size).min(data.len() - done);
+            let (device, memory_address) = self.locate(address);
+            let mut write = memory_address;
+            write.extend_from_slice(&data[done..done + len]);
+            self.i2c.write(device, &write)?;
+            self.wait_for_write_cycle(device)?;
+            done += len;
+        }
+        self.verify(offset, data)
+    }
+
+    /// Check that the EEPROM at `offset` holds `data`.
+    ///
+    /// Fails with [`Error::VerifyFailed`] at the first byte that differs.
+    pub fn verify(&mut self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        let mut contents = vec![0u8; data.len()];
+        self.read(offset, &mut contents)?;
+        match contents.iter().zip(data).position(|(a, b)| a != b) {
+            Some(i) => Err(Error::VerifyFailed {
+                address: offset + i,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The I2C address and memory address bytes for `offset`.
+    fn locate(&self, offset: usize) -> (u8, Vec<u8>) {
+        let block_size = self.part.block_size();
+        let block = (offset / block_size) as u8;
+        let device = self.address + (block << self.part.block_select_shift);
+        let address = offset % block_size;
+        let address_bytes = match self.part.address_bytes() {
+            1 => vec![address as u8],
+            _ => vec![(address >> 8) as u8, address as u8],
+        };
+        (device, address_bytes)
+    }
+
+    /// Wait for the write cycle to finish, by polling until the part
+    /// acknowledges its address again.
+    ///
+    /// A busy part does not acknowledge its address, so only a NACK is retried.
+    /// Any other error is returned at once.
+    fn wait_for_write_cycle(&mut self, device: u8) -> Result<(), Error> {
+        let deadline = Instant::now() + WRITE_CYCLE_TIMEOUT;
+        loop {
+            match self.i2c.write(device, &[]) {
+                Ok(()) => return Ok(()),
+                Err(Error::Nack(_)) if Instant::now() < deadline => {
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                Err(Error::Nack(_)) => {
+                    return Err(Error::BusyTimeout {
+                        timeout: WRITE_CYCLE_TIMEOUT,
+                    });
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), Error> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.part.size => Ok(()),
+            _ => Err(Error::InvalidAddress { address: offset }),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::{cell::RefCell, rc::Rc};
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin, StatefulOutputPin};
+use log::debug;
+
+use crate::{
+    BusPirate, Configuration, Error, IoConfig, IoDirection, LogicLevel, StatusQuery, modes::Dio,
+};
+
+impl embedded_hal::digital::Error for Error {
+    fn kind(&self) -> embedded_hal::digital::ErrorKind {
+        // `Other` is the only kind embedded-hal defines for digital errors.
+        embedded_hal::digital::ErrorKind::Other
+    }
+}
+
+impl BusPirate<Dio> {
+    /// Set the direction of a single IO pin.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pin` is not in the range `0..8`.
+    pub fn set_direction(&mut self, pin: usize, direction: IoDirection) -> Result<(), Error> {
+        let mut io = IoConfig::new();
+        io.set_direction(pin, direction);
+        self.configure(Configuration::builder().io(io).build())
+    }
+
+    /// Make a single IO pin an output driving the given level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pin` is not in the range `0..8`.
+    pub fn drive(&mut self, pin: usize, level: LogicLevel) -> Result<(), Error> {
+        debug!("DIO: IO{pin} {level:?}");
+        let mut io = IoConfig::new();
+        io.set_direction(pin, IoDirection::Output);
+        io.set_level(pin, level);
+        self.configure(Configuration::builder().io(io).build())?;
+        match level {
+            LogicLevel::High => self.mode.driven |= 1 << pin,
+            LogicLevel::Low => self.mode.driven &= !(1 << pin),
+        }
+        Ok(())
+    }
+
+    /// The level last set on a pin with [`drive`](BusPirate::drive), whether
+    /// or not it is still an output. Pins start out set low.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pin` is not in the range `0..8`.
+    pub fn driven_level(&self, pin: usize) -> LogicLevel {
+        assert!(pin < 8, "IO pin must be in the range 0..8");
+        if self.mode.driven & (1 << pin) != 0 {
+            LogicLevel::High
+        } else {
+            LogicLevel::Low
+        }
+    }
+
+    /// Read the levels of all IO pins as a bitmask, where a set bit is high.
+    pub fn read_levels(&mut self) -> Result<u8, Error> {
+        Ok(self.status(&[StatusQuery::Io])?.io_value)
+    }
+
+    /// Split into eight pin handles, IO0 to IO7, that share the Bus Pirate.
+    pub fn split(self) -> [Pin; 8] {
+        let bus = Rc::new(RefCell::new(self));
+        std::array::from_fn(|index| Pin {
+            index,
+            bus: Rc::clone(&bus),
+        })
+    }
+
+    /// Reassemble the Bus Pirate from the pins returned by [`split`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pins did not all come from the same call to [`split`].
+    ///
+    /// [`split`]: BusPirate::split
+    pub fn unsplit(pins: [Pin; 8]) -> Self {
+        let [first, rest @ ..] = pins;
+        assert!(
+            rest.iter().all(|pin| Rc::ptr_eq(&pin.bus, &first.bus)),
+            "Pins must come from the same Bus Pirate"
+        );
+        drop(rest);
+        let Ok(bus) = Rc::try_unwrap(first.bus) else {
+            panic!("Pins must come from the same Bus Pirate");
+        };
+        bus.into_inner()
+    }
+}
+
+/// A single Bus Pirate IO pin in DIO mode.
+///
+/// Each pin operation is a separate round trip to the Bus Pirate.
+pub struct Pin {
+    index: usize,
+    bus: Rc<RefCell<BusPirate<Dio>>>,
+}
+
+impl Pin {
+    /// The pin's number, from 0 to 7.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Stop driving the pin, making it an input.
+    pub fn set_input(&mut self) -> Result<(), Error> {
+        self.bus
+            .borrow_mut()
+            .set_direction(self.index, IoDirection::Input)
+    }
+
+    fn drive(&mut self, level: LogicLevel) -> Result<(), Error> {
+        self.bus.borrow_mut().drive(self.index, level)
+    }
+
+    fn read_high(&mut self) -> Result<bool, Error> {
+        let levels = self.bus.borrow_mut().read_levels()?;
+        Ok(levels & (1 << self.index) != 0)
+    }
+
+    fn driven_high(&self) -> bool {
+        matches!(self.bus.borrow().driven_level(self.index), LogicLevel::High)
+    }
+}
+
+impl ErrorType for Pin {
+    type Error = Error;
+}
+
+impl InputPin for Pin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.read_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.read_high().map(|high| !high)
+    }
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.drive(LogicLevel::Low)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.drive(LogicLevel::High)
+    }
+}
+
+impl StatefulOutputPin for Pin {
+    // The level measured on the pin can differ from the one being driven, for
+    // example when the pin is shorted or overloaded, so report the level set.
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.driven_high())
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.driven_high())
+    }
+}
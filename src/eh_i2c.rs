@@ -1,14 +1,17 @@
-use std::mem::{discriminant, Discriminant};
-
 use embedded_hal::i2c::{ErrorType, I2c, Operation};
 use log::{debug, trace};
 
-use crate::{bpio::I2cRequest, error::Error, modes, BusPirate};
+use crate::{
+    BusPirate,
+    bpio::{I2cRequest, copy_received},
+    error::Error,
+    modes,
+    util::chunk_ranges,
+};
 
 trait I2cAddress {
     fn for_reading(&self) -> u8;
     fn for_writing(&self) -> u8;
-    fn for_operation(&self, op: &Operation<'_>) -> u8;
 }
 
 impl I2cAddress for u8 {
@@ -19,13 +22,6 @@ impl I2cAddress for u8 {
     fn for_writing(&self) -> u8 {
         self << 1
     }
-
-    fn for_operation(&self, op: &Operation<'_>) -> u8 {
-        match op {
-            Operation::Read(_) => self.for_reading(),
-            Operation::Write(_) => self.for_writing(),
-        }
-    }
 }
 
 impl ErrorType for BusPirate<modes::I2c> {
@@ -43,76 +39,164 @@ fn summarise_operations_for_log(operations: &[Operation<'_>]) -> String {
         .join(" ")
 }
 
+/// Consecutive operations of the same kind, which need no start condition
+/// between them.
+enum Phase<'a> {
+    /// The bytes of all the write operations, in order.
+    Write(Vec<u8>),
+    /// The buffers of all the read operations, in order.
+    Read(Vec<&'a mut [u8]>),
+}
+
+fn phases<'a>(operations: &'a mut [Operation<'_>]) -> Vec<Phase<'a>> {
+    let mut phases: Vec<Phase<'a>> = Vec::new();
+    for operation in operations {
+        match operation {
+            Operation::Write(bytes) => {
+                if let Some(Phase::Write(data)) = phases.last_mut() {
+                    data.extend_from_slice(bytes);
+                } else {
+                    phases.push(Phase::Write(bytes.to_vec()));
+                }
+            }
+            Operation::Read(buf) => {
+                if let Some(Phase::Read(bufs)) = phases.last_mut() {
+                    bufs.push(&mut **buf);
+                } else {
+                    phases.push(Phase::Read(vec![&mut **buf]));
+                }
+            }
+        }
+    }
+    phases
+}
+
+impl BusPirate<modes::I2c> {
+    /// Largest number of data bytes per write request, leaving room for the address.
+    fn i2c_max_write(&self) -> usize {
+        self.limits.max_write.saturating_sub(1).max(1)
+    }
+
+    /// Issue a (repeated) start and the address, then write `write` (if any)
+    /// and read into `read`, in as few requests as the transfer limits allow.
+    ///
+    /// After a write that fits in one request, the Bus Pirate turns the bus
+    /// around for the read with a repeated start, so the write and the start
+    /// of the read share a request. A longer write is followed by a separate
+    /// repeated start for the read. If `stop` is set, the last request issues
+    /// a stop.
+    fn i2c_segment(
+        &mut self,
+        address: u8,
+        write: Option<&[u8]>,
+        read: &mut [u8],
+        stop: bool,
+    ) -> Result<(), Error> {
+        let bytes = write.unwrap_or_default();
+        let write_chunks: Vec<_> = match write {
+            Some(bytes) => chunk_ranges(bytes.len(), self.i2c_max_write()).collect(),
+            None => Vec::new(),
+        };
+        let mut read_chunks = chunk_ranges(read.len(), self.limits.max_read);
+
+        // Each request is the address to start with, if any, and its ranges of
+        // `bytes` and `read`.
+        let mut requests = Vec::new();
+        if let [only] = &write_chunks[..] {
+            // The firmware turns the bus around for a read using the first byte
+            // the request wrote, so a write can only share a request with the
+            // first read chunk when that byte is the address.
+            let first_read = read_chunks.next().expect("At least one chunk.");
+            requests.push((Some(address.for_writing()), only.clone(), first_read));
+        } else {
+            requests.extend(
+                write_chunks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, w)| ((i == 0).then_some(address.for_writing()), w.clone(), 0..0)),
+            );
+        }
+        // Otherwise the read starts afresh, addressed for reading.
+        let mut read_start = (write_chunks.len() != 1).then_some(address.for_reading());
+        for r in read_chunks {
+            if r.is_empty() && !requests.is_empty() {
+                continue;
+            }
+            requests.push((read_start.take(), 0..0, r));
+        }
+
+        let last = requests.len() - 1;
+        for (i, (start, w, r)) in requests.into_iter().enumerate() {
+            let request = I2cRequest::builder()
+                .start(start.is_some())
+                .stop(stop && i == last)
+                .maybe_address(start)
+                .bytes_to_write(&bytes[w])
+                .bytes_to_read(r.len())
+                .build();
+            trace!("{request:?}");
+            let received = self.send_data_request(request)?;
+            copy_received(received, &mut read[r])?;
+        }
+        Ok(())
+    }
+
+    /// Carry out the operations of a transaction, with a stop at the end.
+    ///
+    /// Each write and any read straight after it go in the same requests, so
+    /// new requests are only needed for a read followed by a write, or for
+    /// transfers too large for a single request.
+    fn i2c_operations(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let mut phases = phases(operations).into_iter().peekable();
+        while let Some(phase) = phases.next() {
+            let (write, mut bufs) = match phase {
+                Phase::Write(bytes) => match phases.next_if(|p| matches!(p, Phase::Read(_))) {
+                    Some(Phase::Read(bufs)) => (Some(bytes), bufs),
+                    _ => (Some(bytes), Vec::new()),
+                },
+                Phase::Read(bufs) => (None, bufs),
+            };
+            let stop = phases.peek().is_none();
+
+            let mut read = vec![0; bufs.iter().map(|buf| buf.len()).sum()];
+            self.i2c_segment(address, write.as_deref(), &mut read, stop)?;
+            let mut received = &read[..];
+            for buf in &mut bufs {
+                let (data, rest) = received.split_at(buf.len());
+                buf.copy_from_slice(data);
+                received = rest;
+            }
+        }
+        Ok(())
+    }
+
+    /// If `result` is an error, release the bus before returning it.
+    fn stop_on_error<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        if result.is_err() {
+            // A failed stop is ignored, as we're already in an error state.
+            let _ = self.i2c_stop();
+        }
+        result
+    }
+}
+
 impl I2c for BusPirate<modes::I2c> {
     fn transaction(
         &mut self,
         address: u8,
         operations: &mut [Operation<'_>],
     ) -> Result<(), Self::Error> {
-        // TODO: Audit error points for I2C transaction cleanup.
         debug!(
             "I2C transaction: {address:#X} {}",
             summarise_operations_for_log(operations)
         );
 
-        type PreviousOp<'a> = Option<Discriminant<Operation<'a>>>;
-        // Track the type (Read/Write) of the previous I2C operation to allow
-        // for operation coalescing.
-        let mut previous_operation: PreviousOp = None;
-        // A Start condition is needed when:
-        // - It is the first operation (previous_operation is None).
-        // - The previous operation is of a different type to the current operation.
-        fn needs_start<'a>(po: PreviousOp<'a>, op: &Operation<'a>) -> bool {
-            !po.is_some_and(|po| discriminant(op) == po)
-        }
-
-        for operation in operations {
-            // Build I2C request.
-            // Performing the same builder steps, in the same order, even where the
-            // values are redundant, is important to keep the types the same.
-            let request = match operation {
-                Operation::Read(read_buffer) => I2cRequest::builder()
-                    .stop(false)
-                    .bytes_to_read(read_buffer.len())
-                    .bytes_to_write(&[]),
-                Operation::Write(bytes_to_write) => I2cRequest::builder()
-                    .stop(false)
-                    .bytes_to_read(0)
-                    .bytes_to_write(bytes_to_write),
-            };
-            // If we're issuing a start, we also need to supply the address. If we're not,
-            // the address was already sent on the bus. This logic is the same for
-            // both reading and writing.
-            let request = if needs_start(previous_operation, operation) {
-                // If we're issuing a start, we also need to supply the address.
-                // If we're not, then the address was already sent.
-                request
-                    .start(true)
-                    .address(address.for_operation(operation))
-                    .build()
-            } else {
-                request.start(false).build()
-            };
-            trace!("{request:?}");
-
-            // Update the previous operation for Repeated-Start purposes.
-            previous_operation = Some(discriminant(operation));
-
-            let Ok(read_data) = self.send_data_request(request) else {
-                // Does this also need I2C transaction cleanup (issue Stop)?
-                todo!("Handle transfer error.");
-            };
-
-            if let Operation::Read(read_buffer) = operation {
-                // if Some(read_data) = i2c_resp.data()
-                let Some(data) = read_data else {
-                    todo!("Missing data, return an error.");
-                };
-                read_buffer.copy_from_slice(&data);
-            }
-        }
-
-        self.i2c_stop()
+        let result = self.i2c_operations(address, operations);
+        self.stop_on_error(result)
     }
 
     fn write_read(
@@ -121,7 +205,6 @@ impl I2c for BusPirate<modes::I2c> {
         write: &[u8],
         read: &mut [u8],
     ) -> Result<(), Self::Error> {
-        // TODO: What if `read` is longer than u16::MAX?
         debug!(
             "I2C Write-Read to {:#X} w:{} r:{}",
             address,
@@ -129,56 +212,19 @@ impl I2c for BusPirate<modes::I2c> {
             read.len()
         );
 
-        let request = I2cRequest::builder()
-            .start(true)
-            .stop(true)
-            .address(address.for_writing())
-            .bytes_to_write(write)
-            .bytes_to_read(read.len())
-            .build();
-
-        // TODO: Handle mismatched amounts of read data
-        if let Some(data) = self.send_data_request(request)? {
-            read.copy_from_slice(&data);
-            Ok(())
-        } else {
-            eprintln!("Couldn't get data response from contents.");
-            Err(Error::Other)
-        }
+        let result = self.i2c_segment(address, Some(write), read, true);
+        self.stop_on_error(result)
     }
 
     fn read(&mut self, address: u8, read: &mut [u8]) -> Result<(), Self::Error> {
         debug!("I2C Read to {:#X} r:{}", address, read.len());
-
-        let request = I2cRequest::builder()
-            .start(true)
-            .stop(true)
-            .address(address.for_reading())
-            .bytes_to_read(read.len())
-            .build();
-
-        if let Some(data) = self.send_data_request(request)? {
-            read.copy_from_slice(&data);
-            Ok(())
-        } else if read.is_empty() {
-            // No data received, but didn't expect any.
-            Ok(())
-        } else {
-            Err(Error::NoDataReceived)
-        }
+        let result = self.i2c_segment(address, None, read, true);
+        self.stop_on_error(result)
     }
 
     fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
         debug!("I2C Write to {:#X} w:{}", address, write.len());
-
-        let request = I2cRequest::builder()
-            .start(true)
-            .stop(true)
-            .address(address.for_writing())
-            .bytes_to_write(write)
-            .build();
-
-        let _ = self.send_data_request(request)?;
-        Ok(())
+        let result = self.i2c_segment(address, Some(write), &mut [], true);
+        self.stop_on_error(result)
     }
 }
@@ -3,11 +3,17 @@ use std::time::Duration;
 use embedded_hal::spi::{Operation, SpiBus, SpiDevice};
 use log::debug;
 
-use crate::{BusPirate, Error, bpio::DataRequest, modes::Spi};
+use crate::{
+    BusPirate, Error,
+    bpio::{DataRequest, copy_received},
+    modes::Spi,
+    util::chunk_ranges,
+};
 
 impl embedded_hal::spi::Error for Error {
     fn kind(&self) -> embedded_hal::spi::ErrorKind {
-        // TODO: This is a dummy implementation.
+        // None of the SPI-specific kinds (overrun, mode fault, frame format or
+        // chip select fault) are reported by the firmware.
         embedded_hal::spi::ErrorKind::Other
     }
 }
@@ -16,73 +22,265 @@ impl embedded_hal::spi::ErrorType for BusPirate<Spi> {
     type Error = Error;
 }
 
-fn copy(received: Option<Vec<u8>>, buf: &mut [u8]) -> Result<(), Error> {
-    if let Some(data) = received {
-        buf.copy_from_slice(&data);
-        Ok(())
-    } else if buf.is_empty() {
-        // No data received, but we didn't expect any.
+/// Part of an [`SpiDevice`] transaction that is sent as a single transfer.
+enum Segment<'a> {
+    /// Bytes written, then buffers read in order (half-duplex).
+    WriteRead {
+        write: Vec<u8>,
+        read: Vec<&'a mut [u8]>,
+    },
+    /// Bytes written while reading (full-duplex), with the buffers to fill and
+    /// the offset of each one's data in the bytes read.
+    Transfer {
+        write: Vec<u8>,
+        read: Vec<(usize, &'a mut [u8])>,
+    },
+    /// A delay in nanoseconds, with chip select asserted.
+    Delay(u32),
+}
+
+/// Merge the operations of a transaction into as few segments as possible.
+///
+/// Writes followed by reads share a half-duplex segment. Transfers share a
+/// full-duplex segment, which also takes any writes before them and any reads
+/// or writes after them. Delays split segments.
+fn segments<'a>(operations: &'a mut [Operation<'_, u8>]) -> Vec<Segment<'a>> {
+    let mut segments: Vec<Segment<'a>> = Vec::new();
+    for operation in operations {
+        match operation {
+            Operation::Write(bytes) => match segments.last_mut() {
+                Some(Segment::WriteRead { write, read }) if read.is_empty() => {
+                    write.extend_from_slice(bytes);
+                }
+                Some(Segment::Transfer { write, .. }) => write.extend_from_slice(bytes),
+                _ => segments.push(Segment::WriteRead {
+                    write: bytes.to_vec(),
+                    read: Vec::new(),
+                }),
+            },
+            Operation::Read(buf) => match segments.last_mut() {
+                Some(Segment::WriteRead { read, .. }) => read.push(&mut **buf),
+                Some(Segment::Transfer { write, read }) => {
+                    let offset = write.len();
+                    write.resize(offset + buf.len(), 0xFF);
+                    read.push((offset, &mut **buf));
+                }
+                _ => segments.push(Segment::WriteRead {
+                    write: Vec::new(),
+                    read: vec![&mut **buf],
+                }),
+            },
+            Operation::Transfer(read_buf, write_bytes) => {
+                let (write, read) = full_duplex(&mut segments);
+                let offset = write.len();
+                write.extend_from_slice(write_bytes);
+                // Clock out ones for the rest of a read longer than the write.
+                write.resize(offset + read_buf.len().max(write_bytes.len()), 0xFF);
+                read.push((offset, &mut **read_buf));
+            }
+            Operation::TransferInPlace(words) => {
+                let (write, read) = full_duplex(&mut segments);
+                let offset = write.len();
+                write.extend_from_slice(words);
+                read.push((offset, &mut **words));
+            }
+            Operation::DelayNs(ns) => segments.push(Segment::Delay(*ns)),
+        }
+    }
+    segments
+}
+
+/// The full-duplex segment at the end of `segments`, added if necessary.
+fn full_duplex<'s, 'a>(
+    segments: &'s mut Vec<Segment<'a>>,
+) -> (&'s mut Vec<u8>, &'s mut Vec<(usize, &'a mut [u8])>) {
+    match segments.pop() {
+        // Writes alone can be clocked out full-duplex, ignoring what is read.
+        Some(Segment::WriteRead { write, read }) if read.is_empty() => {
+            segments.push(Segment::Transfer {
+                write,
+                read: Vec::new(),
+            });
+        }
+        Some(segment @ Segment::Transfer { .. }) => segments.push(segment),
+        previous => {
+            segments.extend(previous);
+            segments.push(Segment::Transfer {
+                write: Vec::new(),
+                read: Vec::new(),
+            });
+        }
+    }
+    match segments.last_mut() {
+        Some(Segment::Transfer { write, read }) => (write, read),
+        _ => unreachable!("The last segment is full-duplex."),
+    }
+}
+
+impl BusPirate<Spi> {
+    /// Write `write`, then read into `read` (half-duplex), in as many requests as
+    /// the transfer limits require.
+    ///
+    /// Chip select is asserted by the first request if `start` is set, and
+    /// released by the last request if `stop` is set.
+    pub(crate) fn spi_write_read(
+        &mut self,
+        write: &[u8],
+        read: &mut [u8],
+        start: bool,
+        stop: bool,
+    ) -> Result<(), Error> {
+        let write_chunks: Vec<_> = chunk_ranges(write.len(), self.limits.max_write).collect();
+        let read_chunks: Vec<_> = chunk_ranges(read.len(), self.limits.max_read).collect();
+        // The last write chunk shares a request with the first read chunk.
+        let (last_write, write_chunks) = write_chunks.split_last().expect("At least one chunk.");
+        let (first_read, read_chunks) = read_chunks.split_first().expect("At least one chunk.");
+        let requests: Vec<_> = write_chunks
+            .iter()
+            .map(|w| (w.clone(), 0..0))
+            .chain([(last_write.clone(), first_read.clone())])
+            .chain(read_chunks.iter().map(|r| (0..0, r.clone())))
+            .collect();
+
+        let last = requests.len() - 1;
+        let result = requests
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(i, (w, r))| {
+                let request = DataRequest::builder()
+                    .start(start && i == 0)
+                    .stop(stop && i == last)
+                    .bytes_to_read(r.len())
+                    .bytes_to_write(&write[w])
+                    .build();
+                self.send_data_request(request)
+                    .and_then(|received| copy_received(received, &mut read[r]))
+            });
+        self.release_on_error(result, stop)
+    }
+
+    /// Write `write` while reading into `read` (full-duplex), in as many requests
+    /// as the transfer limits require.
+    ///
+    /// Chip select is released by the last request if `stop` is set.
+    fn spi_transfer(&mut self, write: &[u8], read: &mut [u8], stop: bool) -> Result<(), Error> {
+        let chunk_size = self.limits.max_write.min(self.limits.max_read);
+        let chunks: Vec<_> = chunk_ranges(write.len().max(read.len()), chunk_size).collect();
+
+        let last = chunks.len() - 1;
+        let result = chunks.into_iter().enumerate().try_for_each(|(i, c)| {
+            let w = c.start.min(write.len())..c.end.min(write.len());
+            let r = c.start.min(read.len())..c.end.min(read.len());
+            // start_alt or { reads bytes as a byte is written (full-duplex).
+            // Chip select stays asserted between chunks.
+            let request = DataRequest::builder()
+                .start(false)
+                .start_alt(true)
+                .stop(stop && i == last)
+                .bytes_to_read(r.len())
+                .bytes_to_write(&write[w])
+                .build();
+            self.send_data_request(request)
+                .and_then(|received| copy_received(received, &mut read[r]))
+        });
+        self.release_on_error(result, stop)
+    }
+
+    /// Full-duplex transfer that replaces the contents of `words` with the bytes
+    /// read, in as many requests as the transfer limits require.
+    fn spi_transfer_in_place(&mut self, words: &mut [u8], stop: bool) -> Result<(), Error> {
+        let chunk_size = self.limits.max_write.min(self.limits.max_read);
+        let chunks: Vec<_> = chunk_ranges(words.len(), chunk_size).collect();
+
+        let last = chunks.len() - 1;
+        let result = chunks.into_iter().enumerate().try_for_each(|(i, c)| {
+            let write = words[c.clone()].to_vec();
+            let request = DataRequest::builder()
+                .start(false)
+                .start_alt(true)
+                .stop(stop && i == last)
+                .bytes_to_read(c.len())
+                .bytes_to_write(&write)
+                .build();
+            self.send_data_request(request)
+                .and_then(|received| copy_received(received, &mut words[c]))
+        });
+        self.release_on_error(result, stop)
+    }
+
+    /// Send one segment of a transaction.
+    ///
+    /// Chip select is asserted at the start of the segment if `start` is set,
+    /// and released at its end if `stop` is set.
+    fn spi_segment(&mut self, segment: Segment<'_>, start: bool, stop: bool) -> Result<(), Error> {
+        match segment {
+            Segment::WriteRead { write, read } => {
+                let mut received = vec![0; read.iter().map(|buf| buf.len()).sum()];
+                self.spi_write_read(&write, &mut received, start, stop)?;
+                let mut received = &received[..];
+                for buf in read {
+                    let (data, rest) = received.split_at(buf.len());
+                    buf.copy_from_slice(data);
+                    received = rest;
+                }
+            }
+            Segment::Transfer { write, read } => {
+                let mut received = vec![0; write.len()];
+                self.spi_transfer(&write, &mut received, stop)?;
+                for (offset, buf) in read {
+                    buf.copy_from_slice(&received[offset..offset + buf.len()]);
+                }
+            }
+            Segment::Delay(ns) => {
+                if start {
+                    let request = DataRequest::builder().start(true).stop(false).build();
+                    self.send_data_request(request)?;
+                }
+                std::thread::sleep(Duration::from_nanos(ns.into()));
+                if stop {
+                    let request = DataRequest::builder().start(false).stop(true).build();
+                    self.send_data_request(request)?;
+                }
+            }
+        }
         Ok(())
-    } else {
-        Err(Error::NoDataReceived)
+    }
+
+    /// If a transfer failed part way through and `stop` is set, try to release
+    /// chip select.
+    pub(crate) fn release_on_error(
+        &mut self,
+        result: Result<(), Error>,
+        stop: bool,
+    ) -> Result<(), Error> {
+        if result.is_err() && stop {
+            let stop_request = DataRequest::builder().start(false).stop(true).build();
+            // If that fails, ignore it as we're already in an error state.
+            let _ = self.send_data_request(stop_request);
+        }
+        result
     }
 }
 
 impl SpiBus for BusPirate<Spi> {
     fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
         debug!("SPI Read r:{}", words.len());
-
-        let request = DataRequest::builder()
-            .start(true)
-            .stop(true)
-            .bytes_to_read(words.len())
-            .build();
-
-        self.send_data_request(request)
-            .and_then(|received| copy(received, words))
+        self.spi_write_read(&[], words, true, true)
     }
 
     fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
         debug!("SPI Write w:{}", words.len());
-
-        let request = DataRequest::builder()
-            .start(true)
-            .stop(true)
-            .bytes_to_write(words)
-            .build();
-        self.send_data_request(request).map(drop)
+        self.spi_write_read(words, &mut [], true, true)
     }
 
     fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
         debug!("SPI Transfer w:{} r:{}", write.len(), read.len());
-
-        // start_alt or { reads bytes as a byte is written (full-duplex).
-        let request = DataRequest::builder()
-            .start(false)
-            .start_alt(true)
-            .stop(true)
-            .bytes_to_read(read.len())
-            .bytes_to_write(write)
-            .build();
-
-        self.send_data_request(request)
-            .and_then(|received| copy(received, read))
+        self.spi_transfer(write, read, true)
     }
 
     fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
         debug!("SPI Transfer in place w/r:{}", words.len());
-
-        // start_alt or { reads bytes as a byte is written (full-duplex).
-        let request = DataRequest::builder()
-            .start(false)
-            .start_alt(true)
-            .stop(true)
-            .bytes_to_read(words.len())
-            .bytes_to_write(words)
-            .build();
-
-        self.send_data_request(request)
-            .and_then(|received| copy(received, words))
+        self.spi_transfer_in_place(words, true)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
@@ -93,67 +291,19 @@ impl SpiBus for BusPirate<Spi> {
 
 impl SpiDevice for BusPirate<Spi> {
     fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
-        if operations.is_empty() {
+        debug!("SPI Transaction ops:{}", operations.len());
+        let segments = segments(operations);
+        let Some(last) = segments.len().checked_sub(1) else {
             return Ok(());
-        }
-
-        let stop_request = DataRequest::builder().start(false).stop(true).build();
-
-        for op in operations {
-            let res = match op {
-                Operation::Read(read) => {
-                    let req = DataRequest::builder()
-                        .start(true)
-                        .stop(false)
-                        .bytes_to_read(read.len())
-                        .build();
-                    self.send_data_request(req).and_then(|r| copy(r, read))
-                }
-                Operation::Write(write) => {
-                    let req = DataRequest::builder()
-                        .start(true)
-                        .stop(false)
-                        .bytes_to_write(write)
-                        .build();
-                    self.send_data_request(req).map(drop)
-                }
-                Operation::Transfer(read, write) => {
-                    let req = DataRequest::builder()
-                        .start(false)
-                        .start_alt(true)
-                        .stop(false)
-                        .bytes_to_read(read.len())
-                        .bytes_to_write(write)
-                        .build();
-                    self.send_data_request(req).and_then(|r| copy(r, read))
-                }
-                Operation::TransferInPlace(words) => {
-                    let req = DataRequest::builder()
-                        .start(false)
-                        .start_alt(true)
-                        .stop(false)
-                        .bytes_to_read(words.len())
-                        .bytes_to_write(words)
-                        .build();
-                    self.send_data_request(req).and_then(|r| copy(r, words))
-                }
-                Operation::DelayNs(ns) => {
-                    std::thread::sleep(Duration::from_nanos(*ns as u64));
-                    Ok(())
-                }
-            };
-
-            // Try to clean up if there was an error.
-            if let error @ Err(..) = res {
-                // Attempt to release the chip select line.
-                let _ = self.send_data_request(stop_request);
-                // If that fails, ignore it as we're already in an error state.
-                return error;
-            }
-        }
+        };
 
-        // Release the chip select line.
-        self.send_data_request(stop_request).map(drop)
+        let result = segments
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(i, segment)| self.spi_segment(segment, i == 0, i == last));
+        // Only the last segment releases chip select, so make sure it is
+        // released if an earlier one fails. Releasing it twice does no harm.
+        self.release_on_error(result, true)
     }
 
     // For the single-operation methods, just use the SpiBus methods as the implementation
@@ -0,0 +1,93 @@
+use log::debug;
+
+use crate::{BusPirate, Error, buspirate::POLL_INTERVAL, modes::Uart};
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind;
+        match self {
+            Error::Timeout => ErrorKind::TimedOut,
+            Error::Unsupported(_) => ErrorKind::Unsupported,
+            Error::InvalidMode(_) => ErrorKind::InvalidInput,
+            Error::Flatbuffer(_)
+            | Error::Cobs(_)
+            | Error::FrameTooLarge { .. }
+            | Error::TruncatedFrame { .. }
+            | Error::ExcessData { .. } => ErrorKind::InvalidData,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl embedded_hal_nb::serial::Error for Error {
+    fn kind(&self) -> embedded_hal_nb::serial::ErrorKind {
+        // Line errors (overrun, framing, parity and noise) are not reported by
+        // the firmware.
+        embedded_hal_nb::serial::ErrorKind::Other
+    }
+}
+
+impl embedded_io::ErrorType for BusPirate<Uart> {
+    type Error = Error;
+}
+
+impl embedded_io::Read for BusPirate<Uart> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        debug!("UART Read r:{}", buf.len());
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // embedded-io requires blocking until at least one byte is available.
+        loop {
+            match self.read_available(buf)? {
+                0 => std::thread::sleep(POLL_INTERVAL),
+                n => return Ok(n),
+            }
+        }
+    }
+}
+
+impl embedded_io::Write for BusPirate<Uart> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        debug!("UART Write w:{}", buf.len());
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // Writes larger than one request are left to the caller to resend.
+        let len = buf.len().min(self.limits.max_write);
+        self.write_bytes(&buf[..len])?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        // Flush is a no-op because communication with the Bus Pirate is synchronous.
+        Ok(())
+    }
+}
+
+impl embedded_hal_nb::serial::ErrorType for BusPirate<Uart> {
+    type Error = Error;
+}
+
+impl embedded_hal_nb::serial::Read<u8> for BusPirate<Uart> {
+    fn read(&mut self) -> nb::Result<u8, Error> {
+        let mut byte = [0u8];
+        match self.read_available(&mut byte)? {
+            0 => Err(nb::Error::WouldBlock),
+            _ => Ok(byte[0]),
+        }
+    }
+}
+
+impl embedded_hal_nb::serial::Write<u8> for BusPirate<Uart> {
+    fn write(&mut self, word: u8) -> nb::Result<(), Error> {
+        self.write_bytes(&[word])?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Error> {
+        Ok(())
+    }
+}
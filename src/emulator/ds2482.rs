@@ -0,0 +1,253 @@
+use crate::onewire::{ALARM_SEARCH, MATCH_ROM, READ_ROM, RomCode, SEARCH_ROM};
+
+use super::I2cPeripheral;
+
+/// 7-bit address of the DS2482-100, with the AD1 and AD0 pins tied low.
+const ADDRESS: u8 = 0x18;
+
+const DEVICE_RESET: u8 = 0xF0;
+const SET_READ_POINTER: u8 = 0xE1;
+const ONE_WIRE_RESET: u8 = 0xB4;
+const ONE_WIRE_SINGLE_BIT: u8 = 0x87;
+const ONE_WIRE_WRITE_BYTE: u8 = 0xA5;
+const ONE_WIRE_READ_BYTE: u8 = 0x96;
+
+// Read pointer codes.
+const STATUS_REGISTER: u8 = 0xF0;
+const READ_DATA_REGISTER: u8 = 0xE1;
+
+const STATUS_PRESENCE: u8 = 1 << 1;
+const STATUS_RESET: u8 = 1 << 4;
+const STATUS_BIT_RESULT: u8 = 1 << 5;
+
+/// Where a 1-Wire device is in the ROM command layer.
+#[derive(Debug, Clone, Copy)]
+enum RomState {
+    /// Not taking part until the next reset.
+    Idle,
+    /// Receiving the ROM command, with this many bits received so far.
+    Command { bits: u8, value: u8 },
+    /// Searching: sending bit `index` (step 0), then its complement (step 1),
+    /// then receiving the direction the controller chose (step 2).
+    Search { index: usize, step: u8 },
+    /// Sending its ROM code for a read ROM command.
+    Transmit { index: usize },
+    /// Comparing a match ROM command's code with its own.
+    Match { index: usize },
+}
+
+/// A device on the simulated 1-Wire bus, which only implements the ROM
+/// commands.
+#[derive(Debug)]
+struct OneWireDevice {
+    rom: RomCode,
+    alarm: bool,
+    state: RomState,
+}
+
+impl OneWireDevice {
+    fn rom_bit(&self, index: usize) -> bool {
+        self.rom.0[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// The level the device drives in the next time slot: `false` pulls the
+    /// bus low.
+    fn drive(&self) -> bool {
+        match self.state {
+            RomState::Search { index, step: 0 } | RomState::Transmit { index } => {
+                self.rom_bit(index)
+            }
+            RomState::Search { index, step: 1 } => !self.rom_bit(index),
+            _ => true,
+        }
+    }
+
+    /// Take part in a time slot in which the bus was at `level`.
+    fn slot(&mut self, level: bool) {
+        self.state = match self.state {
+            RomState::Idle => RomState::Idle,
+            RomState::Command { bits, value } => {
+                let value = value | (u8::from(level) << bits);
+                match (bits + 1, value) {
+                    (8, SEARCH_ROM) => RomState::Search { index: 0, step: 0 },
+                    (8, ALARM_SEARCH) if self.alarm => RomState::Search { index: 0, step: 0 },
+                    (8, READ_ROM) => RomState::Transmit { index: 0 },
+                    (8, MATCH_ROM) => RomState::Match { index: 0 },
+                    (8, _) => RomState::Idle,
+                    (bits, value) => RomState::Command { bits, value },
+                }
+            }
+            RomState::Search { index, step } if step < 2 => RomState::Search {
+                index,
+                step: step + 1,
+            },
+            // Devices that do not match the direction drop out.
+            RomState::Search { index, .. } if index < 63 && level == self.rom_bit(index) => {
+                RomState::Search {
+                    index: index + 1,
+                    step: 0,
+                }
+            }
+            RomState::Transmit { index } if index < 63 => RomState::Transmit { index: index + 1 },
+            RomState::Match { index } if index < 63 && level == self.rom_bit(index) => {
+                RomState::Match { index: index + 1 }
+            }
+            // A device that has sent or matched its whole ROM code is
+            // selected, but function commands are not modelled.
+            _ => RomState::Idle,
+        };
+    }
+}
+
+/// A DS2482-100 I2C to 1-Wire bridge, with devices on its 1-Wire bus.
+///
+/// The bridge supports the device reset, set read pointer, 1-Wire reset,
+/// single bit, write byte and read byte commands. Time slots complete at once,
+/// so it is never busy. The 1-Wire devices answer the ROM commands: search,
+/// alarm search, read ROM and match ROM.
+pub struct Ds2482 {
+    devices: Vec<OneWireDevice>,
+    status: u8,
+    read_data: u8,
+    read_pointer: u8,
+    /// Bytes written since the I2C start.
+    command: Vec<u8>,
+}
+
+impl Ds2482 {
+    /// A bridge with nothing on its 1-Wire bus, just after power-on.
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            status: STATUS_RESET,
+            read_data: 0,
+            read_pointer: STATUS_REGISTER,
+            command: Vec::new(),
+        }
+    }
+
+    /// Attach a 1-Wire device with the given ROM code.
+    pub fn with_device(self, rom: RomCode) -> Self {
+        self.with(rom, false)
+    }
+
+    /// Attach a 1-Wire device with an active alarm condition, which takes part
+    /// in alarm searches.
+    pub fn with_alarm_device(self, rom: RomCode) -> Self {
+        self.with(rom, true)
+    }
+
+    fn with(mut self, rom: RomCode, alarm: bool) -> Self {
+        self.devices.push(OneWireDevice {
+            rom,
+            alarm,
+            state: RomState::Idle,
+        });
+        self
+    }
+
+    /// A time slot in which the bridge drives `level`: a read slot is a write
+    /// slot of a one. Returns the level sampled on the bus.
+    fn slot(&mut self, level: bool) -> bool {
+        let level = level && self.devices.iter().all(OneWireDevice::drive);
+        for device in &mut self.devices {
+            device.slot(level);
+        }
+        level
+    }
+
+    fn write_byte(&mut self, byte: u8) -> u8 {
+        (0..8).fold(0, |read, bit| {
+            read | (u8::from(self.slot(byte & (1 << bit) != 0)) << bit)
+        })
+    }
+
+    /// Carry out a complete command.
+    fn execute(&mut self, command: u8, parameter: Option<u8>) {
+        // The result bits of the last 1-Wire command are replaced, and the
+        // device reset bit is kept.
+        let status = self.status & STATUS_RESET;
+        match (command, parameter) {
+            (DEVICE_RESET, _) => {
+                self.status = STATUS_RESET;
+                self.read_data = 0;
+                for device in &mut self.devices {
+                    device.state = RomState::Idle;
+                }
+            }
+            (SET_READ_POINTER, Some(pointer)) => {
+                self.read_pointer = pointer;
+                return;
+            }
+            (ONE_WIRE_RESET, _) => {
+                for device in &mut self.devices {
+                    device.state = RomState::Command { bits: 0, value: 0 };
+                }
+                let presence = !self.devices.is_empty();
+                self.status = status | if presence { STATUS_PRESENCE } else { 0 };
+            }
+            (ONE_WIRE_SINGLE_BIT, Some(bit)) => {
+                let level = self.slot(bit & 0x80 != 0);
+                self.status = status | if level { STATUS_BIT_RESULT } else { 0 };
+            }
+            (ONE_WIRE_WRITE_BYTE, Some(byte)) => {
+                self.write_byte(byte);
+                self.status = status;
+            }
+            (ONE_WIRE_READ_BYTE, _) => {
+                self.read_data = self.write_byte(0xFF);
+                self.status = status;
+            }
+            _ => {}
+        }
+        self.read_pointer = STATUS_REGISTER;
+    }
+}
+
+impl Default for Ds2482 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I2cPeripheral for Ds2482 {
+    fn responds_to(&self, address: u8) -> bool {
+        address == ADDRESS
+    }
+
+    fn start(&mut self, _address: u8, _read: bool) -> bool {
+        self.command.clear();
+        true
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        self.command.push(byte);
+        let complete = match self.command[..] {
+            [command @ (DEVICE_RESET | ONE_WIRE_RESET | ONE_WIRE_READ_BYTE)] => {
+                Some((command, None))
+            }
+            [SET_READ_POINTER | ONE_WIRE_SINGLE_BIT | ONE_WIRE_WRITE_BYTE] => None,
+            [
+                command @ (SET_READ_POINTER | ONE_WIRE_SINGLE_BIT | ONE_WIRE_WRITE_BYTE),
+                parameter,
+            ] => Some((command, Some(parameter))),
+            // Unknown commands and extra bytes are not acknowledged.
+            _ => return false,
+        };
+        if let Some((command, parameter)) = complete {
+            self.execute(command, parameter);
+        }
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        match self.read_pointer {
+            READ_DATA_REGISTER => self.read_data,
+            _ => self.status,
+        }
+    }
+
+    fn stop(&mut self) {
+        self.command.clear();
+    }
+}
@@ -0,0 +1,186 @@
+use super::I2cPeripheral;
+
+/// Base 7-bit address of 24xx EEPROMs, with the A2..A0 pins tied low.
+const BASE_ADDRESS: u8 = 0x50;
+
+/// Where the EEPROM is in a write transaction.
+#[derive(Debug, Clone, Copy)]
+enum WriteState {
+    /// Receiving the memory address, with this many bytes still to come.
+    Address(usize),
+    /// Receiving data to program.
+    Data,
+}
+
+/// A 24xx-family I2C EEPROM.
+///
+/// Parts up to 2KiB take a one-byte memory address and larger parts a
+/// two-byte address. Any memory beyond that range is selected by the low bits
+/// of the I2C address, so the part answers to several consecutive addresses,
+/// or by higher bits with [`with_block_select_shift`](Self::with_block_select_shift).
+///
+/// Writes are programmed when the stop condition arrives and wrap within their
+/// page, as on real parts. Reads continue sequentially, wrapping at the end of
+/// the memory.
+pub struct Eeprom24 {
+    base_address: u8,
+    block_select_shift: u32,
+    memory: Vec<u8>,
+    page_size: usize,
+    address_bytes: usize,
+    pointer: usize,
+    state: Option<WriteState>,
+    /// Bytes received for programming, in order.
+    pending: Vec<u8>,
+    /// Address of the first pending byte.
+    pending_start: usize,
+    write_cycle_polls: u32,
+    busy_polls: u32,
+}
+
+impl Eeprom24 {
+    /// A blank (all 0xFF) EEPROM of `size` bytes with `page_size`-byte pages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero or `page_size` does not divide it.
+    pub fn new(size: usize, page_size: usize) -> Self {
+        assert!(size > 0, "EEPROM size must not be zero");
+        assert!(
+            page_size > 0 && size.is_multiple_of(page_size),
+            "Page size must divide the EEPROM size"
+        );
+        Self {
+            base_address: BASE_ADDRESS,
+            block_select_shift: 0,
+            memory: vec![0xFF; size],
+            page_size,
+            address_bytes: if size > 2048 { 2 } else { 1 },
+            pointer: 0,
+            state: None,
+            pending: Vec::new(),
+            pending_start: 0,
+            write_cycle_polls: 0,
+            busy_polls: 0,
+        }
+    }
+
+    /// Set the 7-bit address, for parts with the A2..A0 pins tied high.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.base_address = address;
+        self
+    }
+
+    /// Select blocks of memory with the I2C address bits from bit `shift` up,
+    /// as the 24xx1025 does with bit 2.
+    pub fn with_block_select_shift(mut self, shift: u32) -> Self {
+        self.block_select_shift = shift;
+        self
+    }
+
+    /// Ignore this many address attempts after each write, to exercise
+    /// acknowledge polling during the write cycle.
+    pub fn with_write_cycle_polls(mut self, polls: u32) -> Self {
+        self.write_cycle_polls = polls;
+        self
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn contents_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Memory selected by the address bytes alone.
+    fn block_size(&self) -> usize {
+        1 << (8 * self.address_bytes)
+    }
+
+    fn blocks(&self) -> usize {
+        self.memory.len().div_ceil(self.block_size())
+    }
+
+    /// The block of memory selected by an I2C address, if any.
+    fn block(&self, address: u8) -> Option<usize> {
+        let offset = usize::from(address.checked_sub(self.base_address)?);
+        let block = offset >> self.block_select_shift;
+        (block << self.block_select_shift == offset && block < self.blocks()).then_some(block)
+    }
+
+    fn program(&mut self) {
+        let page_start = self.pending_start - self.pending_start % self.page_size;
+        let offset = self.pending_start - page_start;
+        // Bytes past the end of the page wrap around to its start, with later
+        // bytes overwriting earlier ones.
+        for (i, &byte) in self.pending.iter().enumerate() {
+            let address = page_start + (offset + i) % self.page_size;
+            self.memory[address] = byte;
+        }
+        // The address counter ends up after the last byte written.
+        self.pointer = page_start + (offset + self.pending.len()) % self.page_size;
+        self.pending.clear();
+        self.busy_polls = self.write_cycle_polls;
+    }
+}
+
+impl I2cPeripheral for Eeprom24 {
+    fn responds_to(&self, address: u8) -> bool {
+        self.block(address).is_some()
+    }
+
+    fn start(&mut self, address: u8, read: bool) -> bool {
+        if self.busy_polls > 0 {
+            // Still in the write cycle.
+            self.busy_polls -= 1;
+            return false;
+        }
+        if read {
+            self.state = None;
+        } else {
+            let block = self
+                .block(address)
+                .expect("Only addressed when it responds");
+            self.pointer = block * self.block_size();
+            self.state = Some(WriteState::Address(self.address_bytes));
+            self.pending.clear();
+        }
+        true
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        match self.state {
+            Some(WriteState::Address(remaining)) => {
+                let shift = 8 * (remaining - 1);
+                self.pointer |= usize::from(byte) << shift;
+                self.pointer %= self.memory.len();
+                self.state = Some(if remaining > 1 {
+                    WriteState::Address(remaining - 1)
+                } else {
+                    self.pending_start = self.pointer;
+                    WriteState::Data
+                });
+                true
+            }
+            Some(WriteState::Data) => {
+                self.pending.push(byte);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn read(&mut self) -> u8 {
+        let byte = self.memory[self.pointer];
+        self.pointer = (self.pointer + 1) % self.memory.len();
+        byte
+    }
+
+    fn stop(&mut self) {
+        if matches!(self.state, Some(WriteState::Data)) && !self.pending.is_empty() {
+            self.program();
+        }
+        self.state = None;
+    }
+}
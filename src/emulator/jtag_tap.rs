@@ -0,0 +1,97 @@
+use crate::jtag::{JtagPins, TapState};
+
+use super::IoPeripheral;
+
+const IR_LENGTH: usize = 4;
+const IDCODE_LENGTH: usize = 32;
+/// The all-ones instruction, which selects the bypass register.
+const BYPASS: u64 = 0b1111;
+/// Loaded by Capture-IR. The two low bits are fixed at `01` by IEEE 1149.1.
+const IR_CAPTURE: u64 = 0b0001;
+
+/// A single JTAG device with a 4-bit instruction register, on the IO pins.
+///
+/// The only data registers are the IDCODE and the 1-bit bypass register.
+/// BYPASS (all ones) selects the bypass register; any other instruction, and
+/// a reset of the TAP controller, selects the IDCODE.
+pub struct JtagTap {
+    pins: JtagPins,
+    idcode: u32,
+    state: TapState,
+    tck: bool,
+    bypass: bool,
+    /// The register being shifted, least significant bit nearest TDO.
+    register: u64,
+    length: usize,
+    shifted: usize,
+}
+
+impl JtagTap {
+    /// A device with the given IDCODE, on the default pins.
+    pub fn new(idcode: u32) -> Self {
+        Self {
+            pins: JtagPins::default(),
+            idcode,
+            state: TapState::TestLogicReset,
+            tck: false,
+            bypass: false,
+            register: 0,
+            length: 1,
+            shifted: 0,
+        }
+    }
+
+    /// Move the device to other pins.
+    pub fn with_pins(mut self, pins: JtagPins) -> Self {
+        self.pins = pins;
+        self
+    }
+
+    /// The state of the device's TAP controller.
+    pub fn state(&self) -> TapState {
+        self.state
+    }
+
+    /// How many bits have been shifted through the instruction and data
+    /// registers in total.
+    pub fn shifted_bits(&self) -> usize {
+        self.shifted
+    }
+
+    fn rising_edge(&mut self, tms: bool, tdi: bool) {
+        if matches!(self.state, TapState::ShiftDr | TapState::ShiftIr) {
+            self.register = (self.register >> 1) | (u64::from(tdi) << (self.length - 1));
+            self.shifted += 1;
+        }
+        self.state = self.state.next(tms);
+        match self.state {
+            TapState::TestLogicReset => self.bypass = false,
+            TapState::CaptureIr => (self.register, self.length) = (IR_CAPTURE, IR_LENGTH),
+            TapState::CaptureDr if self.bypass => (self.register, self.length) = (0, 1),
+            TapState::CaptureDr => {
+                (self.register, self.length) = (u64::from(self.idcode), IDCODE_LENGTH)
+            }
+            TapState::UpdateIr => self.bypass = self.register == BYPASS,
+            _ => {}
+        }
+    }
+}
+
+impl IoPeripheral for JtagTap {
+    fn update(&mut self, levels: u8) -> u8 {
+        let pin = |pin: usize| levels & (1 << pin) != 0;
+        let tck = pin(self.pins.tck);
+        if tck && !self.tck {
+            self.rising_edge(pin(self.pins.tms), pin(self.pins.tdi));
+        }
+        self.tck = tck;
+
+        // TDO is only driven while shifting, and otherwise pulled up.
+        let tdo = match self.state {
+            TapState::ShiftDr | TapState::ShiftIr => self.register & 1 != 0,
+            _ => true,
+        };
+        let mask = 1 << self.pins.tdo;
+        if tdo { levels | mask } else { levels & !mask }
+    }
+}
@@ -0,0 +1,575 @@
+//! A software Bus Pirate, for testing without hardware.
+//!
+//! [`Emulator`] is a [`Transport`] that decodes BPIO2 request packets and
+//! answers them as the firmware would, with simulated peripherals attached to
+//! the I2C and SPI buses and the IO pins. Pass it to
+//! [`BusPirate::from_transport`] in place of a serial port.
+//!
+//! Peripherals are moved into the emulator. To inspect one during a test, wrap
+//! it in `Arc<Mutex<_>>` and keep a clone.
+//!
+//! [`Transport`]: crate::Transport
+//! [`BusPirate::from_transport`]: crate::BusPirate::from_transport
+
+mod ds2482;
+mod eeprom;
+mod jtag_tap;
+mod nor_flash;
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use bpio2 as generated;
+use flatbuffers::{FlatBufferBuilder, UnionWIPOffset, WIPOffset};
+use log::{debug, trace};
+
+use crate::bpio::VERSION_MAJOR;
+use crate::modes::Modes;
+
+pub use ds2482::Ds2482;
+pub use eeprom::Eeprom24;
+pub use jtag_tap::JtagTap;
+pub use nor_flash::SpiNorFlash;
+
+/// BPIO2 interface minor version reported by the emulator.
+const VERSION_MINOR: u16 = 0;
+
+// Transfer limits reported for every mode.
+const MAX_PACKET_SIZE: u32 = 640;
+const MAX_WRITE: u32 = 512;
+const MAX_READ: u32 = 512;
+
+// Error messages returned in responses.
+const I2C_ADDRESS_NACK: &str = "I2C address NACK";
+const I2C_DATA_NACK: &str = "I2C data NACK";
+
+/// A simulated device on the emulator's I2C bus.
+pub trait I2cPeripheral: Send {
+    /// Returns `true` if the device responds to the 7-bit `address`.
+    fn responds_to(&self, address: u8) -> bool;
+
+    /// A start or repeated start addressed to the device, for reading if `read`
+    /// is set. Returns `true` if the device acknowledges its address.
+    fn start(&mut self, address: u8, read: bool) -> bool;
+
+    /// A byte written by the controller. Returns `true` to acknowledge it.
+    fn write(&mut self, byte: u8) -> bool;
+
+    /// A byte read by the controller.
+    fn read(&mut self) -> u8;
+
+    /// A stop condition, ending the device's transaction.
+    fn stop(&mut self);
+}
+
+/// A simulated device on the emulator's SPI bus.
+pub trait SpiPeripheral: Send {
+    /// Chip select was asserted.
+    fn select(&mut self);
+
+    /// Receive `mosi` from the controller, returning the byte sent back.
+    fn transfer(&mut self, mosi: u8) -> u8;
+
+    /// Chip select was released.
+    fn deselect(&mut self);
+}
+
+/// A simulated device on the emulator's IO pins.
+pub trait IoPeripheral: Send {
+    /// The Bus Pirate changed its pins to `levels`, one bit per pin. Returns
+    /// the levels with the device's own outputs applied.
+    fn update(&mut self, levels: u8) -> u8;
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T: I2cPeripheral> I2cPeripheral for Arc<Mutex<T>> {
+    fn responds_to(&self, address: u8) -> bool {
+        lock(self).responds_to(address)
+    }
+
+    fn start(&mut self, address: u8, read: bool) -> bool {
+        lock(self).start(address, read)
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        lock(self).write(byte)
+    }
+
+    fn read(&mut self) -> u8 {
+        lock(self).read()
+    }
+
+    fn stop(&mut self) {
+        lock(self).stop()
+    }
+}
+
+impl<T: IoPeripheral> IoPeripheral for Arc<Mutex<T>> {
+    fn update(&mut self, levels: u8) -> u8 {
+        lock(self).update(levels)
+    }
+}
+
+impl<T: SpiPeripheral> SpiPeripheral for Arc<Mutex<T>> {
+    fn select(&mut self) {
+        lock(self).select()
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        lock(self).transfer(mosi)
+    }
+
+    fn deselect(&mut self) {
+        lock(self).deselect()
+    }
+}
+
+/// The I2C device currently addressed.
+#[derive(Debug, Clone, Copy)]
+struct I2cTarget {
+    index: usize,
+    read: bool,
+}
+
+/// A software Bus Pirate that answers BPIO2 requests.
+pub struct Emulator {
+    mode: Modes,
+    bit_order_msb: bool,
+    psu_enabled: bool,
+    psu_set_mv: u32,
+    psu_set_ma: u32,
+    pullup_enabled: bool,
+    io_direction: u8,
+    io_value: u8,
+    i2c_devices: Vec<Box<dyn I2cPeripheral>>,
+    i2c_target: Option<I2cTarget>,
+    spi_device: Option<Box<dyn SpiPeripheral>>,
+    spi_selected: bool,
+    io_device: Option<Box<dyn IoPeripheral>>,
+    /// Bytes written by the host that do not yet form a complete frame.
+    incoming: Vec<u8>,
+    /// Encoded response frames waiting to be read by the host.
+    outgoing: VecDeque<u8>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emulator {
+    /// An emulator in HiZ mode, with nothing attached to its buses.
+    pub fn new() -> Self {
+        Self {
+            mode: Modes::HiZ,
+            bit_order_msb: true,
+            psu_enabled: false,
+            psu_set_mv: 0,
+            psu_set_ma: 0,
+            pullup_enabled: false,
+            io_direction: 0,
+            io_value: 0,
+            i2c_devices: Vec::new(),
+            i2c_target: None,
+            spi_device: None,
+            spi_selected: false,
+            io_device: None,
+            incoming: Vec::new(),
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    /// Attach a device to the I2C bus.
+    ///
+    /// If several devices respond to an address, the first attached wins.
+    pub fn with_i2c_device(mut self, device: impl I2cPeripheral + 'static) -> Self {
+        self.i2c_devices.push(Box::new(device));
+        self
+    }
+
+    /// Attach a device to the SPI bus, replacing any existing one.
+    pub fn with_spi_device(mut self, device: impl SpiPeripheral + 'static) -> Self {
+        self.spi_device = Some(Box::new(device));
+        self
+    }
+
+    /// Attach a device to the IO pins, replacing any existing one.
+    pub fn with_io_device(mut self, device: impl IoPeripheral + 'static) -> Self {
+        self.io_device = Some(Box::new(device));
+        self
+    }
+
+    /// Decode a COBS frame, including its sentinel, and queue the response.
+    fn handle_frame(&mut self, frame: &[u8]) {
+        let mut packet = vec![0u8; frame.len()];
+        let mut decoder = cobs::CobsDecoder::new(&mut packet);
+        let response = match decoder.push(frame) {
+            Ok(Some(report)) => {
+                packet.truncate(report.frame_size());
+                self.handle_packet(&packet)
+            }
+            _ => error_packet("Invalid COBS frame"),
+        };
+        trace!("Emulator: {}-byte response packet", response.len());
+        self.outgoing.extend(cobs::encode_vec(&response));
+        self.outgoing.push_back(0x00);
+    }
+
+    fn handle_packet(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let Ok(packet) = generated::root_as_request_packet(bytes) else {
+            return error_packet("Invalid request packet");
+        };
+        if packet.version_major() != VERSION_MAJOR {
+            return error_packet("Unsupported BPIO version");
+        }
+
+        let mut fbb = FlatBufferBuilder::new();
+        let (contents_type, contents) = if packet.contents_as_status_request().is_some() {
+            (
+                generated::ResponsePacketContents::StatusResponse,
+                self.status_response(&mut fbb),
+            )
+        } else if let Some(request) = packet.contents_as_configuration_request() {
+            (
+                generated::ResponsePacketContents::ConfigurationResponse,
+                self.configuration_response(&mut fbb, request),
+            )
+        } else if let Some(request) = packet.contents_as_data_request() {
+            (
+                generated::ResponsePacketContents::DataResponse,
+                self.data_response(&mut fbb, request),
+            )
+        } else {
+            return error_packet("Unknown request type");
+        };
+
+        let mut response = generated::ResponsePacketBuilder::new(&mut fbb);
+        response.add_contents_type(contents_type);
+        response.add_contents(contents);
+        let response = response.finish();
+        fbb.finish_minimal(response);
+        fbb.finished_data().to_vec()
+    }
+
+    fn status_response(&self, fbb: &mut FlatBufferBuilder<'_>) -> WIPOffset<UnionWIPOffset> {
+        let modes: Vec<_> = Modes::ALL
+            .iter()
+            .map(|mode| fbb.create_string(mode.name()))
+            .collect();
+        let modes_available = fbb.create_vector(&modes);
+        let mode_current = fbb.create_string(self.mode.name());
+
+        let mut status = generated::StatusResponseBuilder::new(fbb);
+        status.add_version_flatbuffers_major(VERSION_MAJOR);
+        status.add_version_flatbuffers_minor(VERSION_MINOR);
+        status.add_modes_available(modes_available);
+        status.add_mode_current(mode_current);
+        status.add_mode_bitorder_msb(self.bit_order_msb);
+        status.add_mode_max_packet_size(MAX_PACKET_SIZE);
+        status.add_mode_max_write(MAX_WRITE);
+        status.add_mode_max_read(MAX_READ);
+        status.add_psu_enabled(self.psu_enabled);
+        status.add_psu_set_mv(self.psu_set_mv);
+        status.add_psu_set_ma(self.psu_set_ma);
+        status.add_pullup_enabled(self.pullup_enabled);
+        status.add_io_direction(self.io_direction);
+        status.add_io_value(self.io_value);
+        status.finish().as_union_value()
+    }
+
+    fn configuration_response(
+        &mut self,
+        fbb: &mut FlatBufferBuilder<'_>,
+        request: generated::ConfigurationRequest<'_>,
+    ) -> WIPOffset<UnionWIPOffset> {
+        let error = self
+            .configure(request)
+            .err()
+            .map(|message| fbb.create_string(message));
+
+        let mut response = generated::ConfigurationResponseBuilder::new(fbb);
+        if let Some(error) = error {
+            response.add_error(error);
+        }
+        response.finish().as_union_value()
+    }
+
+    fn configure(
+        &mut self,
+        request: generated::ConfigurationRequest<'_>,
+    ) -> Result<(), &'static str> {
+        if let Some(name) = request.mode() {
+            let mode = Modes::ALL
+                .into_iter()
+                .find(|mode| mode.name() == name)
+                .ok_or("Invalid mode name")?;
+            self.change_mode(mode);
+        }
+        if request.mode_bitorder_msb() {
+            self.bit_order_msb = true;
+        }
+        if request.mode_bitorder_lsb() {
+            self.bit_order_msb = false;
+        }
+        if request.psu_enable() {
+            self.psu_enabled = true;
+        }
+        if request.psu_disable() {
+            self.psu_enabled = false;
+        }
+        if request.psu_set_mv() != 0 {
+            self.psu_set_mv = request.psu_set_mv();
+        }
+        if request.psu_set_ma() != 0 {
+            self.psu_set_ma = u32::from(request.psu_set_ma());
+        }
+        if request.pullup_enable() {
+            self.pullup_enabled = true;
+        }
+        if request.pullup_disable() {
+            self.pullup_enabled = false;
+        }
+        let direction_mask = request.io_direction_mask();
+        self.io_direction =
+            (self.io_direction & !direction_mask) | (request.io_direction() & direction_mask);
+        let value_mask = request.io_value_mask();
+        self.io_value = (self.io_value & !value_mask) | (request.io_value() & value_mask);
+        if let Some(device) = &mut self.io_device {
+            self.io_value = device.update(self.io_value);
+        }
+        Ok(())
+    }
+
+    fn change_mode(&mut self, mode: Modes) {
+        debug!("Emulator: {} -> {}", self.mode, mode);
+        // Leave the buses idle.
+        self.i2c_stop();
+        self.spi_deselect();
+        self.mode = mode;
+    }
+
+    fn data_response(
+        &mut self,
+        fbb: &mut FlatBufferBuilder<'_>,
+        request: generated::DataRequest<'_>,
+    ) -> WIPOffset<UnionWIPOffset> {
+        let (data, error) = match self.transfer(request) {
+            Ok(data) => (Some(data).filter(|d| !d.is_empty()), None),
+            Err(message) => (None, Some(message)),
+        };
+        let data = data.map(|data| fbb.create_vector(&data));
+        let error = error.map(|message| fbb.create_string(message));
+
+        let mut response = generated::DataResponseBuilder::new(fbb);
+        if let Some(data) = data {
+            response.add_data_read(data);
+        }
+        if let Some(error) = error {
+            response.add_error(error);
+        }
+        response.finish().as_union_value()
+    }
+
+    fn transfer(&mut self, request: generated::DataRequest<'_>) -> Result<Vec<u8>, &'static str> {
+        let write: Vec<u8> = request
+            .data_write()
+            .map(|v| v.iter().collect())
+            .unwrap_or_default();
+        let read_len = usize::from(request.bytes_read());
+        if write.len() > MAX_WRITE as usize || read_len > MAX_READ as usize {
+            return Err("Transfer too large");
+        }
+        trace!(
+            "Emulator: {} data w:{} r:{}",
+            self.mode,
+            write.len(),
+            read_len
+        );
+
+        match self.mode {
+            Modes::HiZ => Err("Data requests are not available in HiZ mode"),
+            Modes::I2c => {
+                let result = self.i2c_transfer(request.start_main(), &write, read_len);
+                // The bus is released as requested, even after an error.
+                if request.stop_main() {
+                    self.i2c_stop();
+                }
+                result
+            }
+            Modes::Spi => {
+                if request.start_main() || request.start_alt() {
+                    self.spi_select();
+                }
+                let read = self.spi_transfer(&write, read_len, request.start_alt());
+                if request.stop_main() {
+                    self.spi_deselect();
+                }
+                Ok(read)
+            }
+            // Nothing is attached to the raw buses, so writes are discarded
+            // and reads see the idle high data line.
+            Modes::TwoWire | Modes::ThreeWire => Ok(vec![0xFF; read_len]),
+            // Nothing is attached in other modes, so writes are discarded and
+            // there is nothing to read.
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn i2c_transfer(
+        &mut self,
+        start: bool,
+        request_write: &[u8],
+        read_len: usize,
+    ) -> Result<Vec<u8>, &'static str> {
+        let mut write = request_write;
+        if start {
+            // The address is the first byte written after a start.
+            let (&address, rest) = write.split_first().ok_or("I2C start without an address")?;
+            self.i2c_start(address)?;
+            write = rest;
+        }
+
+        for &byte in write {
+            let target = self.i2c_target.ok_or("No I2C device addressed")?;
+            if target.read {
+                return Err("Cannot write to an I2C device being read");
+            }
+            if !self.i2c_devices[target.index].write(byte) {
+                return Err(I2C_DATA_NACK);
+            }
+        }
+
+        if read_len == 0 {
+            return Ok(Vec::new());
+        }
+        let target = match self.i2c_target {
+            Some(target) if target.read => target,
+            // Like the firmware, turn the bus around with a repeated start to
+            // the first byte this request wrote, as a read address. Only when
+            // the request started with an address is that the device's address.
+            _ => {
+                let &address = request_write.first().ok_or("I2C read without an address")?;
+                self.i2c_start(address | 1)?
+            }
+        };
+        let device = &mut self.i2c_devices[target.index];
+        Ok((0..read_len).map(|_| device.read()).collect())
+    }
+
+    /// Address a device with an 8-bit address byte.
+    fn i2c_start(&mut self, address_byte: u8) -> Result<I2cTarget, &'static str> {
+        let address = address_byte >> 1;
+        let read = address_byte & 1 != 0;
+        self.i2c_target = None;
+
+        let index = self
+            .i2c_devices
+            .iter()
+            .position(|device| device.responds_to(address))
+            .ok_or(I2C_ADDRESS_NACK)?;
+        if !self.i2c_devices[index].start(address, read) {
+            return Err(I2C_ADDRESS_NACK);
+        }
+        let target = I2cTarget { index, read };
+        self.i2c_target = Some(target);
+        Ok(target)
+    }
+
+    fn i2c_stop(&mut self) {
+        if let Some(target) = self.i2c_target.take() {
+            self.i2c_devices[target.index].stop();
+        }
+    }
+
+    /// Clock bytes on the SPI bus. In full-duplex mode a byte is read for each
+    /// byte written; otherwise `write` is sent first and then `read_len` bytes
+    /// are read.
+    fn spi_transfer(&mut self, write: &[u8], read_len: usize, full_duplex: bool) -> Vec<u8> {
+        let mut read = Vec::with_capacity(read_len);
+        if full_duplex {
+            for i in 0..write.len().max(read_len) {
+                let miso = self.spi_exchange(write.get(i).copied().unwrap_or(0xFF));
+                if read.len() < read_len {
+                    read.push(miso);
+                }
+            }
+        } else {
+            for &byte in write {
+                self.spi_exchange(byte);
+            }
+            for _ in 0..read_len {
+                read.push(self.spi_exchange(0xFF));
+            }
+        }
+        read
+    }
+
+    fn spi_exchange(&mut self, mosi: u8) -> u8 {
+        match &mut self.spi_device {
+            Some(device) if self.spi_selected => device.transfer(mosi),
+            // MISO is pulled high when no device is driving it.
+            _ => 0xFF,
+        }
+    }
+
+    fn spi_select(&mut self) {
+        if !self.spi_selected {
+            self.spi_selected = true;
+            if let Some(device) = &mut self.spi_device {
+                device.select();
+            }
+        }
+    }
+
+    fn spi_deselect(&mut self) {
+        if self.spi_selected {
+            self.spi_selected = false;
+            if let Some(device) = &mut self.spi_device {
+                device.deselect();
+            }
+        }
+    }
+}
+
+/// A response packet carrying only an error message.
+fn error_packet(message: &str) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+    let error = fbb.create_string(message);
+    let mut response = generated::ResponsePacketBuilder::new(&mut fbb);
+    response.add_error(error);
+    let response = response.finish();
+    fbb.finish_minimal(response);
+    fbb.finished_data().to_vec()
+}
+
+impl Write for Emulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.incoming.push(byte);
+            if byte == 0x00 {
+                let frame = std::mem::take(&mut self.incoming);
+                self.handle_frame(&frame);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Emulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.outgoing.is_empty() {
+            // No request is waiting for a response.
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.outgoing.read(buf)
+    }
+}
@@ -0,0 +1,258 @@
+use super::SpiPeripheral;
+
+const WRITE_STATUS: u8 = 0x01;
+const PAGE_PROGRAM: u8 = 0x02;
+const READ: u8 = 0x03;
+const WRITE_DISABLE: u8 = 0x04;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const FAST_READ: u8 = 0x0B;
+const SECTOR_ERASE: u8 = 0x20;
+const READ_SFDP: u8 = 0x5A;
+const CHIP_ERASE: u8 = 0x60;
+const JEDEC_ID: u8 = 0x9F;
+const CHIP_ERASE_ALT: u8 = 0xC7;
+const BLOCK_ERASE: u8 = 0xD8;
+
+const STATUS_BUSY: u8 = 1 << 0;
+const STATUS_WRITE_ENABLED: u8 = 1 << 1;
+/// Block protect bits, BP2..BP0.
+const STATUS_BLOCK_PROTECT: u8 = 0b0001_1100;
+/// Bits that can be changed with the write status command.
+const STATUS_WRITABLE: u8 = 0b1011_1100;
+
+const PAGE_SIZE: usize = 256;
+const SECTOR_SIZE: usize = 4 * 1024;
+const BLOCK_SIZE: usize = 64 * 1024;
+/// Where the basic flash parameter table starts in the SFDP data.
+const SFDP_TABLE_OFFSET: usize = 0x30;
+
+/// A 25-series SPI NOR flash with 3-byte addressing.
+///
+/// The model supports the common command set: JEDEC ID, SFDP, read, fast
+/// read, page program, 4KiB sector, 64KiB block and chip erase, and the status
+/// register. Programming can only clear bits, and program and erase commands
+/// need a preceding write enable.
+///
+/// Any set block protect bit protects the whole array, rather than the
+/// fraction of it a real part would protect.
+pub struct SpiNorFlash {
+    jedec_id: [u8; 3],
+    memory: Vec<u8>,
+    sfdp: Vec<u8>,
+    status: u8,
+    busy_polls: u32,
+    busy_remaining: u32,
+    /// Bytes received since chip select was asserted.
+    command: Vec<u8>,
+}
+
+impl SpiNorFlash {
+    /// A blank (all 0xFF) flash of `size` bytes, reporting `jedec_id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not a multiple of 64KiB or exceeds 16MiB, the limit
+    /// of 3-byte addressing.
+    pub fn new(jedec_id: [u8; 3], size: usize) -> Self {
+        assert!(
+            size > 0 && size.is_multiple_of(BLOCK_SIZE) && size <= 1 << 24,
+            "Flash size must be a multiple of 64KiB, up to 16MiB"
+        );
+        Self {
+            jedec_id,
+            memory: vec![0xFF; size],
+            sfdp: sfdp_table(size),
+            status: 0,
+            busy_polls: 1,
+            busy_remaining: 0,
+            command: Vec::new(),
+        }
+    }
+
+    /// Report busy for this many status reads after each program or erase,
+    /// to exercise polling. The default is one.
+    pub fn with_busy_polls(mut self, polls: u32) -> Self {
+        self.busy_polls = polls;
+        self
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn contents_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    /// The 3-byte address following the opcode, if it has been received.
+    fn address(&self) -> Option<usize> {
+        match self.command[..] {
+            [_, a2, a1, a0, ..] => {
+                let address = (usize::from(a2) << 16) | (usize::from(a1) << 8) | usize::from(a0);
+                Some(address % self.memory.len())
+            }
+            _ => None,
+        }
+    }
+
+    fn busy(&self) -> bool {
+        self.status & STATUS_BUSY != 0
+    }
+
+    /// The byte sent back while receiving byte `index` of the command.
+    fn output(&mut self, index: usize) -> u8 {
+        let opcode = self.command[0];
+        match opcode {
+            READ_STATUS => {
+                let status = self.status;
+                if self.busy() {
+                    if self.busy_remaining > 0 {
+                        self.busy_remaining -= 1;
+                    } else {
+                        self.status &= !STATUS_BUSY;
+                    }
+                }
+                status
+            }
+            _ if self.busy() => 0xFF,
+            JEDEC_ID => self.jedec_id.get(index - 1).copied().unwrap_or(0xFF),
+            READ if index >= 4 => self.read_byte(index - 4),
+            FAST_READ if index >= 5 => self.read_byte(index - 5),
+            READ_SFDP if index >= 5 => {
+                let address = self.address().unwrap_or_default() + index - 5;
+                self.sfdp.get(address).copied().unwrap_or(0xFF)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn read_byte(&self, offset: usize) -> u8 {
+        let address = self.address().unwrap_or_default();
+        self.memory[(address + offset) % self.memory.len()]
+    }
+
+    /// Act on a command once chip select is released.
+    fn execute(&mut self) {
+        let Some(&opcode) = self.command.first() else {
+            return;
+        };
+        if self.busy() {
+            return;
+        }
+        match opcode {
+            WRITE_ENABLE => self.status |= STATUS_WRITE_ENABLED,
+            WRITE_DISABLE => self.status &= !STATUS_WRITE_ENABLED,
+            WRITE_STATUS => {
+                if let Some(&value) = self.command.get(1) {
+                    self.modify(|flash| {
+                        flash.status =
+                            (flash.status & !STATUS_WRITABLE) | (value & STATUS_WRITABLE);
+                    });
+                }
+            }
+            PAGE_PROGRAM => {
+                if let Some(address) = self.address() {
+                    self.modify_array(|flash| flash.program(address));
+                }
+            }
+            SECTOR_ERASE => self.erase(SECTOR_SIZE),
+            BLOCK_ERASE => self.erase(BLOCK_SIZE),
+            CHIP_ERASE | CHIP_ERASE_ALT => self.modify_array(|flash| flash.memory.fill(0xFF)),
+            _ => {}
+        }
+    }
+
+    /// Run a write-enabled operation, which then clears the write enable latch
+    /// and starts a write cycle.
+    fn modify(&mut self, operation: impl FnOnce(&mut Self)) {
+        if self.status & STATUS_WRITE_ENABLED == 0 {
+            return;
+        }
+        operation(self);
+        self.status &= !STATUS_WRITE_ENABLED;
+        self.status |= STATUS_BUSY;
+        self.busy_remaining = self.busy_polls.saturating_sub(1);
+        if self.busy_polls == 0 {
+            self.status &= !STATUS_BUSY;
+        }
+    }
+
+    /// As [`modify`](Self::modify), but ignored if the array is protected.
+    fn modify_array(&mut self, operation: impl FnOnce(&mut Self)) {
+        if self.status & STATUS_BLOCK_PROTECT != 0 {
+            self.status &= !STATUS_WRITE_ENABLED;
+            return;
+        }
+        self.modify(operation);
+    }
+
+    fn program(&mut self, address: usize) {
+        let page_start = address - address % PAGE_SIZE;
+        let data = &self.command[4..];
+        // Only the last page's worth of data is kept, and it wraps within the
+        // page.
+        let skip = data.len().saturating_sub(PAGE_SIZE);
+        for (i, &byte) in data.iter().enumerate().skip(skip) {
+            let target = page_start + (address - page_start + i) % PAGE_SIZE;
+            self.memory[target] &= byte;
+        }
+    }
+
+    fn erase(&mut self, size: usize) {
+        if let Some(address) = self.address() {
+            let start = address - address % size;
+            self.modify_array(|flash| flash.memory[start..start + size].fill(0xFF));
+        }
+    }
+}
+
+impl SpiPeripheral for SpiNorFlash {
+    fn select(&mut self) {
+        self.command.clear();
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        self.command.push(mosi);
+        let index = self.command.len() - 1;
+        if index == 0 {
+            // Nothing is driven while the opcode arrives.
+            return 0xFF;
+        }
+        self.output(index)
+    }
+
+    fn deselect(&mut self) {
+        self.execute();
+        self.command.clear();
+    }
+}
+
+/// JESD216B SFDP data with a header and a basic flash parameter table.
+fn sfdp_table(size: usize) -> Vec<u8> {
+    let mut dwords = [0u32; 16];
+    // Uniform 4KiB erase with opcode 0x20, and page writes of 64 bytes or more.
+    dwords[0] = 0b01 | (1 << 2) | (u32::from(SECTOR_ERASE) << 8);
+    // Density in bits, minus one.
+    dwords[1] = (size as u32 * 8) - 1;
+    // Erase types 1 and 2, as a power of two size and an opcode.
+    dwords[7] = 12 | (u32::from(SECTOR_ERASE) << 8) | (16 << 16) | (u32::from(BLOCK_ERASE) << 24);
+    // Page size as a power of two.
+    dwords[10] = PAGE_SIZE.trailing_zeros() << 4;
+
+    let mut sfdp = vec![0xFF; SFDP_TABLE_OFFSET];
+    // Signature, revision 1.6, one parameter header and an unused byte.
+    sfdp[0..8].copy_from_slice(&[b'S', b'F', b'D', b'P', 0x06, 0x01, 0x00, 0xFF]);
+    // Basic flash parameter table: ID, revision 1.6, length in dwords and a
+    // pointer to the table.
+    let [pointer @ .., _] = (SFDP_TABLE_OFFSET as u32).to_le_bytes();
+    sfdp[8..12].copy_from_slice(&[0x00, 0x06, 0x01, dwords.len() as u8]);
+    sfdp[12..15].copy_from_slice(&pointer);
+    sfdp[15] = 0xFF;
+    sfdp.extend(dwords.iter().flat_map(|dword| dword.to_le_bytes()));
+    sfdp
+}
@@ -1,3 +1,9 @@
+use std::time::Duration;
+
+use embedded_hal::i2c::NoAcknowledgeSource;
+
+use crate::BpioVersion;
+
 #[derive(Debug)]
 pub enum Error {
     SerialPort(serialport::Error),
@@ -5,12 +11,115 @@ pub enum Error {
     Flatbuffer(flatbuffers::InvalidFlatbuffer),
     Cobs(cobs::DecodeError),
     FlatbufferUnexpectedContents,
+    /// An error reported by the firmware that isn't one of the recognised kinds.
     BpioErrorMessage(String),
     UnexpectedResponseType(&'static str),
-    NoDataReceived,
+    /// Less data was received than was requested.
+    ShortRead {
+        expected: usize,
+        got: usize,
+    },
+    /// More data was received than was requested.
+    ExcessData {
+        limit: usize,
+        got: usize,
+    },
+    /// An I2C target did not acknowledge its address or a data byte.
+    Nack(NoAcknowledgeSource),
+    /// The bus was not in the expected state, for example a line was held low.
+    BusError,
+    /// Another I2C controller won arbitration for the bus.
+    ArbitrationLoss,
+    /// No device responded with a presence pulse.
+    NoDevice,
+    /// The mode is not available, or the request is not valid in this mode.
+    InvalidMode(String),
+    /// The power supply could not be configured or tripped its current limit.
+    PsuFault(String),
+    /// No complete response arrived before the response deadline.
+    Timeout,
+    /// A response frame was larger than the maximum packet size, in bytes.
+    FrameTooLarge {
+        limit: usize,
+    },
+    /// The connection ended part way through a response frame.
+    TruncatedFrame {
+        received: usize,
+    },
+    /// The Bus Pirate's BPIO2 interface version is not supported. Supported
+    /// versions have the same major version as `minimum`, and at least its
+    /// minor version.
+    IncompatibleVersion {
+        found: BpioVersion,
+        minimum: BpioVersion,
+    },
+    /// A CRC check on received data failed.
+    CrcMismatch,
+    /// The operation is not supported by the Bus Pirate's BPIO2 interface, or
+    /// by the attached device.
+    Unsupported(&'static str),
+    /// An address or length is outside a memory, or not suitably aligned.
+    InvalidAddress {
+        address: usize,
+    },
+    /// A memory refused to be erased or written.
+    WriteProtected,
+    /// A device was still busy with an operation after its timeout.
+    BusyTimeout {
+        timeout: Duration,
+    },
+    /// Data read back from a memory differed from the data written, first at
+    /// this address.
+    VerifyFailed {
+        address: usize,
+    },
+    /// An IO pin number is out of range, or the pin is assigned more than once.
+    InvalidPin {
+        pin: usize,
+    },
     Other,
 }
 
+impl Error {
+    /// Classify an error message reported by the firmware.
+    ///
+    /// Error messages are free text rather than part of the BPIO2 schema, and
+    /// the firmware does not document them, so they are classified by keyword.
+    /// The keywords have not been checked against the firmware's own messages,
+    /// and the tests below only pin down the keyword rules. Messages that match
+    /// no keyword are kept whole as [`Error::BpioErrorMessage`], so nothing is
+    /// lost when a guess misses.
+    ///
+    /// More specific conditions are checked first, so that a message such as
+    /// "bus error in I2C mode" is a bus error rather than a mode error.
+    pub(crate) fn from_bpio_message(message: &str) -> Self {
+        let lower = message.to_ascii_lowercase();
+        let has = |word: &str| lower.contains(word);
+
+        if has("nack") || has("no ack") {
+            if has("addr") {
+                Self::Nack(NoAcknowledgeSource::Address)
+            } else if has("data") {
+                Self::Nack(NoAcknowledgeSource::Data)
+            } else {
+                Self::Nack(NoAcknowledgeSource::Unknown)
+            }
+        } else if has("arbitration") {
+            Self::ArbitrationLoss
+        } else if has("presence") || has("no device") {
+            Self::NoDevice
+        } else if has("psu") || has("power supply") || has("current limit") || has("fuse") {
+            Self::PsuFault(message.to_owned())
+        } else if has("bus error") || has("bus fault") || has("held low") {
+            Self::BusError
+        } else if has("mode") {
+            Self::InvalidMode(message.to_owned())
+        } else {
+            Self::BpioErrorMessage(message.to_owned())
+        }
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
         Self::Io(value)
@@ -35,18 +144,145 @@ impl From<cobs::DecodeError> for Error {
     }
 }
 
+impl From<Error> for std::io::Error {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::Io(e) => e,
+            Error::Timeout => std::io::Error::new(std::io::ErrorKind::TimedOut, value),
+            other => std::io::Error::other(other),
+        }
+    }
+}
+
 impl embedded_hal::i2c::Error for Error {
     fn kind(&self) -> embedded_hal::i2c::ErrorKind {
-        // TODO: This is a dummy implementation.
-        embedded_hal::i2c::ErrorKind::Other
+        use embedded_hal::i2c::ErrorKind;
+        match self {
+            Error::Nack(source) => ErrorKind::NoAcknowledge(*source),
+            Error::ArbitrationLoss => ErrorKind::ArbitrationLoss,
+            Error::BusError => ErrorKind::Bus,
+            _ => ErrorKind::Other,
+        }
     }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // TODO: This is a dummy implementation.
-        write!(f, "{:?}", self)
+        match self {
+            Error::SerialPort(e) => write!(f, "serial port error: {e}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Flatbuffer(e) => write!(f, "invalid flatbuffer in response: {e}"),
+            Error::Cobs(e) => write!(f, "invalid COBS frame in response: {e:?}"),
+            Error::FlatbufferUnexpectedContents => write!(f, "unexpected response contents"),
+            Error::BpioErrorMessage(msg) => write!(f, "Bus Pirate reported an error: {msg}"),
+            Error::UnexpectedResponseType(name) => write!(f, "unexpected response type {name}"),
+            Error::ShortRead { expected, got } => {
+                write!(f, "expected {expected} bytes of data but received {got}")
+            }
+            Error::ExcessData { limit, got } => {
+                write!(f, "expected up to {limit} bytes of data but received {got}")
+            }
+            Error::Nack(NoAcknowledgeSource::Address) => {
+                write!(f, "I2C target did not acknowledge its address")
+            }
+            Error::Nack(NoAcknowledgeSource::Data) => {
+                write!(f, "I2C target did not acknowledge data")
+            }
+            Error::Nack(NoAcknowledgeSource::Unknown) => {
+                write!(f, "I2C target did not acknowledge")
+            }
+            Error::BusError => write!(f, "bus error"),
+            Error::ArbitrationLoss => write!(f, "lost arbitration for the I2C bus"),
+            Error::NoDevice => write!(f, "no device present on the bus"),
+            Error::InvalidMode(msg) => write!(f, "invalid mode: {msg}"),
+            Error::PsuFault(msg) => write!(f, "power supply fault: {msg}"),
+            Error::Timeout => write!(f, "timed out waiting for a response"),
+            Error::FrameTooLarge { limit } => {
+                write!(f, "response larger than the {limit}-byte packet limit")
+            }
+            Error::TruncatedFrame { received } => {
+                write!(f, "connection ended after {received} bytes of a response")
+            }
+            Error::IncompatibleVersion { found, minimum } => write!(
+                f,
+                "Bus Pirate uses BPIO2 version {found}, but {minimum} to {}.x is supported",
+                minimum.major
+            ),
+            Error::CrcMismatch => write!(f, "CRC mismatch in received data"),
+            Error::Unsupported(what) => write!(f, "not supported: {what}"),
+            Error::InvalidAddress { address } => write!(f, "invalid address {address:#X}"),
+            Error::WriteProtected => write!(f, "memory is write protected"),
+            Error::BusyTimeout { timeout } => {
+                write!(f, "still busy after {timeout:?}")
+            }
+            Error::VerifyFailed { address } => {
+                write!(f, "verification failed at address {address:#X}")
+            }
+            Error::InvalidPin { pin } => {
+                write!(f, "IO{pin} does not exist or is assigned more than once")
+            }
+            Error::Other => write!(f, "unknown error"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SerialPort(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Flatbuffer(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
-impl std::error::Error for Error {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Made-up messages exercising each keyword rule. They are not the
+    /// firmware's messages, which have not been checked.
+    #[test]
+    fn classify_bpio_messages() {
+        let cases: &[(&str, fn(&Error) -> bool)] = &[
+            ("Address NACK", |e| {
+                matches!(e, Error::Nack(NoAcknowledgeSource::Address))
+            }),
+            ("NACK on data byte", |e| {
+                matches!(e, Error::Nack(NoAcknowledgeSource::Data))
+            }),
+            ("NACK", |e| {
+                matches!(e, Error::Nack(NoAcknowledgeSource::Unknown))
+            }),
+            ("No ACK from device", |e| {
+                matches!(e, Error::Nack(NoAcknowledgeSource::Unknown))
+            }),
+            ("Arbitration lost", |e| matches!(e, Error::ArbitrationLoss)),
+            ("No presence pulse", |e| matches!(e, Error::NoDevice)),
+            ("No device detected", |e| matches!(e, Error::NoDevice)),
+            ("PSU current limit exceeded", |e| {
+                matches!(e, Error::PsuFault(_))
+            }),
+            ("Fuse blown", |e| matches!(e, Error::PsuFault(_))),
+            ("Invalid mode name", |e| matches!(e, Error::InvalidMode(_))),
+            ("Data requests are not available in HiZ mode", |e| {
+                matches!(e, Error::InvalidMode(_))
+            }),
+            ("Bus error: SDA held low", |e| matches!(e, Error::BusError)),
+            ("Bus error in I2C mode", |e| matches!(e, Error::BusError)),
+            (
+                "I2C read without an address",
+                |e| matches!(e, Error::BpioErrorMessage(m) if m == "I2C read without an address"),
+            ),
+            (
+                "Transfer too large",
+                |e| matches!(e, Error::BpioErrorMessage(m) if m == "Transfer too large"),
+            ),
+        ];
+        for (message, expected) in cases {
+            let error = Error::from_bpio_message(message);
+            assert!(expected(&error), "{message:?} classified as {error:?}");
+        }
+    }
+}
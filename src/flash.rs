@@ -0,0 +1,499 @@
+//! Reading, erasing and programming 25-series SPI NOR flash.
+//!
+//! [`SpiFlash`] works over any [`SpiDevice`] that reports this crate's
+//! [`Error`], such as [`BusPirate<Spi>`](crate::BusPirate). The size, page size
+//! and erase sizes are read from the part's SFDP tables (JESD216), so parts
+//! that predate SFDP need their [`FlashInfo`] supplied by hand.
+//!
+//! Only 3-byte addressing is supported, which limits parts to 16MiB.
+
+use std::time::{Duration, Instant};
+
+use embedded_hal::spi::{Operation, SpiDevice};
+use log::debug;
+
+use crate::{Error, buspirate::POLL_INTERVAL};
+
+const WRITE_STATUS: u8 = 0x01;
+const PAGE_PROGRAM: u8 = 0x02;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const FAST_READ: u8 = 0x0B;
+const READ_SFDP: u8 = 0x5A;
+const JEDEC_ID: u8 = 0x9F;
+/// The JEDEC chip erase instruction. Many parts also accept 0xC7.
+const CHIP_ERASE: u8 = 0x60;
+
+/// Write in progress.
+const STATUS_BUSY: u8 = 1 << 0;
+/// Write enable latch.
+const STATUS_WRITE_ENABLED: u8 = 1 << 1;
+/// Block protect bits, BP2..BP0.
+const STATUS_BLOCK_PROTECT: u8 = 0b0001_1100;
+
+/// Largest part that can be addressed with 3-byte addresses.
+const MAX_SIZE: usize = 1 << 24;
+/// Page size of parts whose SFDP tables do not give one.
+const DEFAULT_PAGE_SIZE: usize = 256;
+
+// Generous upper bounds on how long operations take, from typical datasheets.
+const PROGRAM_TIMEOUT: Duration = Duration::from_millis(100);
+const WRITE_STATUS_TIMEOUT: Duration = Duration::from_millis(100);
+const ERASE_TIMEOUT: Duration = Duration::from_secs(5);
+const CHIP_ERASE_TIMEOUT: Duration = Duration::from_secs(400);
+/// The status register is polled about this many times in an operation's
+/// timeout, so long erases are not polled as often as page programs.
+const POLLS_PER_TIMEOUT: u32 = 1000;
+/// Longest wait between status polls.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An erase instruction and the size of the region it erases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EraseType {
+    pub size: usize,
+    pub opcode: u8,
+}
+
+/// The geometry of a flash part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashInfo {
+    /// Manufacturer, memory type and capacity bytes.
+    pub jedec_id: [u8; 3],
+    /// Size in bytes.
+    pub size: usize,
+    /// Largest amount that can be programmed at once, in bytes.
+    pub page_size: usize,
+    /// Supported erase sizes, smallest first.
+    pub erase_types: Vec<EraseType>,
+    /// The chip erase instruction. SFDP tables do not give one, so detection
+    /// assumes the JEDEC standard 0x60; parts that only accept 0xC7 need it
+    /// set by hand.
+    pub chip_erase_opcode: u8,
+}
+
+impl FlashInfo {
+    /// Read the JEDEC ID and SFDP tables of the part on `device`.
+    pub fn detect<D: SpiDevice<Error = Error>>(device: &mut D) -> Result<Self, Error> {
+        let mut jedec_id = [0u8; 3];
+        device.transaction(&mut [
+            Operation::Write(&[JEDEC_ID]),
+            Operation::Read(&mut jedec_id),
+        ])?;
+        debug!("Flash: JEDEC ID {jedec_id:02X?}");
+
+        let mut header = [0u8; 16];
+        read_sfdp(device, 0, &mut header)?;
+        if header[0..4] != *b"SFDP" {
+            return Err(Error::Unsupported("flash without SFDP tables"));
+        }
+        // The first parameter header is always for the basic flash parameter
+        // table, which has at least nine dwords.
+        let length = usize::from(header[11]);
+        if header[8] != 0x00 || length < 9 {
+            return Err(Error::Unsupported(
+                "flash without a basic SFDP parameter table",
+            ));
+        }
+        let pointer = usize::from(header[12])
+            | (usize::from(header[13]) << 8)
+            | (usize::from(header[14]) << 16);
+        let mut table = vec![0u8; length * 4];
+        read_sfdp(device, pointer, &mut table)?;
+        let dwords: Vec<u32> = table
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        let info = Self {
+            jedec_id,
+            size: sfdp_size(dwords[1])?,
+            page_size: sfdp_page_size(&dwords),
+            erase_types: sfdp_erase_types(&dwords)?,
+            chip_erase_opcode: CHIP_ERASE,
+        };
+        debug!("Flash: {info:?}");
+        Ok(info)
+    }
+}
+
+fn read_sfdp<D: SpiDevice<Error = Error>>(
+    device: &mut D,
+    address: usize,
+    buf: &mut [u8],
+) -> Result<(), Error> {
+    let [a2, a1, a0] = address_bytes(address);
+    // The address is followed by a dummy byte.
+    device.transaction(&mut [
+        Operation::Write(&[READ_SFDP, a2, a1, a0, 0x00]),
+        Operation::Read(buf),
+    ])
+}
+
+/// The size in bytes from the density dword of the basic parameter table.
+fn sfdp_size(density: u32) -> Result<usize, Error> {
+    let bits = if density & (1 << 31) == 0 {
+        u64::from(density) + 1
+    } else {
+        // Parts of 4Gbit and larger give the density as a power of two.
+        1u64.checked_shl(density & !(1 << 31)).unwrap_or(u64::MAX)
+    };
+    match usize::try_from(bits / 8) {
+        Ok(size) if size <= MAX_SIZE => Ok(size),
+        _ => Err(Error::Unsupported("flash larger than 16MiB")),
+    }
+}
+
+/// The page size from the basic parameter table.
+///
+/// Tables from before JESD216A stop short of the page size, and a zero exponent
+/// would mean single-byte pages, so both fall back to the usual 256 bytes.
+fn sfdp_page_size(dwords: &[u32]) -> usize {
+    match dwords.get(10).map(|dword| (dword >> 4) & 0xF) {
+        None | Some(0) => DEFAULT_PAGE_SIZE,
+        Some(exponent) => 1 << exponent,
+    }
+}
+
+/// The erase types from the basic parameter table, smallest first.
+fn sfdp_erase_types(dwords: &[u32]) -> Result<Vec<EraseType>, Error> {
+    let mut erase_types = [dwords[7], dwords[8]]
+        .into_iter()
+        .flat_map(|dword| [dword & 0xFFFF, dword >> 16])
+        .filter(|&erase_type| erase_type & 0xFF != 0)
+        .map(|erase_type| {
+            let size = 1usize
+                .checked_shl(erase_type & 0xFF)
+                .filter(|&size| size <= MAX_SIZE)
+                .ok_or(Error::Unsupported("erase type larger than 16MiB"))?;
+            Ok(EraseType {
+                size,
+                opcode: (erase_type >> 8) as u8,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    // Older tables may only describe the 4KiB erase.
+    if erase_types.is_empty() && dwords[0] & 0b11 == 0b01 {
+        erase_types.push(EraseType {
+            size: 4 * 1024,
+            opcode: (dwords[0] >> 8) as u8,
+        });
+    }
+    if erase_types.is_empty() {
+        return Err(Error::Unsupported("flash without an erase instruction"));
+    }
+    erase_types.sort_by_key(|erase_type| erase_type.size);
+    erase_types.dedup_by_key(|erase_type| erase_type.size);
+    Ok(erase_types)
+}
+
+fn address_bytes(address: usize) -> [u8; 3] {
+    [(address >> 16) as u8, (address >> 8) as u8, address as u8]
+}
+
+/// A 25-series SPI NOR flash.
+///
+/// Erasing and programming fail with [`Error::WriteProtected`] while any of
+/// the block protect bits are set, or if the part refuses to enable writes.
+/// Use [`set_write_protection`](Self::set_write_protection) to clear them.
+pub struct SpiFlash<D> {
+    device: D,
+    info: FlashInfo,
+}
+
+impl<D: SpiDevice<Error = Error>> SpiFlash<D> {
+    /// Detect the part on `device` from its SFDP tables.
+    pub fn new(mut device: D) -> Result<Self, Error> {
+        let info = FlashInfo::detect(&mut device)?;
+        Ok(Self::with_info(device, info))
+    }
+
+    /// Use a part whose geometry is already known.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `info` has no erase types, or they are not smallest first.
+    pub fn with_info(device: D, info: FlashInfo) -> Self {
+        assert!(
+            !info.erase_types.is_empty() && info.erase_types.is_sorted_by_key(|t| t.size),
+            "Erase types must be given, smallest first"
+        );
+        Self { device, info }
+    }
+
+    pub fn info(&self) -> &FlashInfo {
+        &self.info
+    }
+
+    /// Give back the SPI device.
+    pub fn release(self) -> D {
+        self.device
+    }
+
+    pub fn read_status(&mut self) -> Result<u8, Error> {
+        let mut status = [0u8];
+        self.device.transaction(&mut [
+            Operation::Write(&[READ_STATUS]),
+            Operation::Read(&mut status),
+        ])?;
+        Ok(status[0])
+    }
+
+    /// Write the status register and wait for the write to finish.
+    pub fn write_status(&mut self, status: u8) -> Result<(), Error> {
+        self.write_enable()?;
+        self.device.write(&[WRITE_STATUS, status])?;
+        self.wait_while_busy(WRITE_STATUS_TIMEOUT)
+    }
+
+    /// Returns `true` if any of the block protect bits are set.
+    pub fn is_write_protected(&mut self) -> Result<bool, Error> {
+        Ok(self.read_status()? & STATUS_BLOCK_PROTECT != 0)
+    }
+
+    /// Set or clear all of the block protect bits.
+    ///
+    /// Fails with [`Error::WriteProtected`] if the status register itself is
+    /// protected, for example by the status register protect bit and the WP
+    /// pin.
+    pub fn set_write_protection(&mut self, protect: bool) -> Result<(), Error> {
+        let status = self.read_status()? & !(STATUS_BUSY | STATUS_WRITE_ENABLED);
+        let wanted = if protect {
+            status | STATUS_BLOCK_PROTECT
+        } else {
+            status & !STATUS_BLOCK_PROTECT
+        };
+        self.write_status(wanted)?;
+        if self.is_write_protected()? != protect {
+            return Err(Error::WriteProtected);
+        }
+        Ok(())
+    }
+
+    /// Read from `address` into `buf`.
+    pub fn read(&mut self, address: usize, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_range(address, buf.len())?;
+        debug!("Flash: read {} bytes at {address:#X}", buf.len());
+        let [a2, a1, a0] = address_bytes(address);
+        // Fast read works at any clock speed, at the cost of a dummy byte.
+        self.device.transaction(&mut [
+            Operation::Write(&[FAST_READ, a2, a1, a0, 0x00]),
+            Operation::Read(buf),
+        ])
+    }
+
+    /// Read the whole part.
+    pub fn read_all(&mut self) -> Result<Vec<u8>, Error> {
+        let mut contents = vec![0u8; self.info.size];
+        self.read(0, &mut contents)?;
+        Ok(contents)
+    }
+
+    /// Erase the smallest erasable region containing `address`.
+    pub fn erase_sector(&mut self, address: usize) -> Result<(), Error> {
+        let erase_type = self.info.erase_types[0];
+        self.erase_with(erase_type, address)
+    }
+
+    /// Erase the largest erasable region containing `address`.
+    pub fn erase_block(&mut self, address: usize) -> Result<(), Error> {
+        let erase_type = self.info.erase_types[self.info.erase_types.len() - 1];
+        self.erase_with(erase_type, address)
+    }
+
+    /// Erase the whole part.
+    pub fn erase_chip(&mut self) -> Result<(), Error> {
+        debug!("Flash: erase chip");
+        self.check_unprotected()?;
+        self.write_enable()?;
+        self.device.write(&[self.info.chip_erase_opcode])?;
+        self.wait_while_busy(CHIP_ERASE_TIMEOUT)
+    }
+
+    /// Erase `len` bytes from `address`, using the largest erase sizes that
+    /// fit.
+    ///
+    /// Both `address` and `len` must be multiples of the smallest erase size.
+    pub fn erase(&mut self, address: usize, len: usize) -> Result<(), Error> {
+        self.check_range(address, len)?;
+        let smallest = self.info.erase_types[0].size;
+        if !address.is_multiple_of(smallest) {
+            return Err(Error::InvalidAddress { address });
+        }
+        if !len.is_multiple_of(smallest) {
+            return Err(Error::InvalidAddress {
+                address: address + len,
+            });
+        }
+
+        let end = address + len;
+        let mut address = address;
+        while address < end {
+            let erase_type = *self
+                .info
+                .erase_types
+                .iter()
+                .rev()
+                .find(|t| address.is_multiple_of(t.size) && address + t.size <= end)
+                .expect("The smallest erase size fits.");
+            self.erase_with(erase_type, address)?;
+            address += erase_type.size;
+        }
+        Ok(())
+    }
+
+    fn erase_with(&mut self, erase_type: EraseType, address: usize) -> Result<(), Error> {
+        self.check_range(address, 1)?;
+        let address = address - address % erase_type.size;
+        debug!("Flash: erase {} bytes at {address:#X}", erase_type.size);
+        self.check_unprotected()?;
+        self.write_enable()?;
+        let [a2, a1, a0] = address_bytes(address);
+        self.device.write(&[erase_type.opcode, a2, a1, a0])?;
+        self.wait_while_busy(ERASE_TIMEOUT)
+    }
+
+    /// Program `data` at `address`, a page at a time.
+    ///
+    /// Programming can only clear bits, so the region should be erased first.
+    /// Pages that would only be programmed with 0xFF are skipped, as that would
+    /// not change them.
+    pub fn program(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        self.check_range(address, data.len())?;
+        debug!("Flash: program {} bytes at {address:#X}", data.len());
+        self.check_unprotected()?;
+
+        let mut offset = 0;
+        while offset < data.len() {
+            let page_address = address + offset;
+            // Programs must not cross a page boundary, or they wrap around.
+            let len =
+                (self.info.page_size - page_address % self.info.page_size).min(data.len() - offset);
+            let page = &data[offset..offset + len];
+            offset += len;
+            if page.iter().all(|&b| b == 0xFF) {
+                continue;
+            }
+
+            self.write_enable()?;
+            let [a2, a1, a0] = address_bytes(page_address);
+            self.device.transaction(&mut [
+                Operation::Write(&[PAGE_PROGRAM, a2, a1, a0]),
+                Operation::Write(page),
+            ])?;
+            self.wait_while_busy(PROGRAM_TIMEOUT)?;
+        }
+        Ok(())
+    }
+
+    /// Check that the flash at `address` holds `data`.
+    ///
+    /// Fails with [`Error::VerifyFailed`] at the first byte that differs.
+    pub fn verify(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        let mut contents = vec![0u8; data.len()];
+        self.read(address, &mut contents)?;
+        match contents.iter().zip(data).position(|(a, b)| a != b) {
+            Some(i) => Err(Error::VerifyFailed {
+                address: address + i,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Erase, program and verify `data` at `address`.
+    ///
+    /// The smallest erasable regions covering the data are erased, so anything
+    /// else in them is lost.
+    pub fn write(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        self.check_range(address, data.len())?;
+        let smallest = self.info.erase_types[0].size;
+        let start = address - address % smallest;
+        let end = (address + data.len()).next_multiple_of(smallest);
+        self.erase(start, end - start)?;
+        self.program(address, data)?;
+        self.verify(address, data)
+    }
+
+    fn check_range(&self, address: usize, len: usize) -> Result<(), Error> {
+        match address.checked_add(len) {
+            Some(end) if end <= self.info.size => Ok(()),
+            _ => Err(Error::InvalidAddress { address }),
+        }
+    }
+
+    fn check_unprotected(&mut self) -> Result<(), Error> {
+        if self.is_write_protected()? {
+            return Err(Error::WriteProtected);
+        }
+        Ok(())
+    }
+
+    /// Set the write enable latch, checking that the part accepted it.
+    fn write_enable(&mut self) -> Result<(), Error> {
+        self.device.write(&[WRITE_ENABLE])?;
+        if self.read_status()? & STATUS_WRITE_ENABLED == 0 {
+            return Err(Error::WriteProtected);
+        }
+        Ok(())
+    }
+
+    /// Poll the status register until the write in progress finishes.
+    ///
+    /// Fails with [`Error::BusyTimeout`] if it is still busy after `timeout`.
+    fn wait_while_busy(&mut self, timeout: Duration) -> Result<(), Error> {
+        let deadline = Instant::now() + timeout;
+        let interval = (timeout / POLLS_PER_TIMEOUT).clamp(POLL_INTERVAL, MAX_POLL_INTERVAL);
+        loop {
+            if self.read_status()? & STATUS_BUSY == 0 {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::BusyTimeout { timeout });
+            }
+            std::thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A basic parameter table with the given erase type dwords and dword 11.
+    fn table(erase_types: [u32; 2], dword11: u32) -> Vec<u32> {
+        let mut dwords = vec![0; 11];
+        dwords[7] = erase_types[0];
+        dwords[8] = erase_types[1];
+        dwords[10] = dword11;
+        dwords
+    }
+
+    #[test]
+    fn erase_types_are_sorted_and_deduplicated() {
+        let dwords = table([0xD810_200C, 0xD810_520F], 0);
+        let sizes: Vec<_> = sfdp_erase_types(&dwords)
+            .unwrap()
+            .iter()
+            .map(|t| t.size)
+            .collect();
+        assert_eq!(sizes, [4 * 1024, 32 * 1024, 64 * 1024]);
+    }
+
+    #[test]
+    fn oversized_erase_types_are_rejected() {
+        for exponent in [25, 31, 63, 64, 255] {
+            let dwords = table([0x2000 | exponent, 0], 0);
+            assert!(
+                matches!(sfdp_erase_types(&dwords), Err(Error::Unsupported(_))),
+                "exponent {exponent}"
+            );
+        }
+    }
+
+    #[test]
+    fn page_size_falls_back_when_missing_or_zero() {
+        assert_eq!(sfdp_page_size(&table([0, 0], 0x80)), 256);
+        assert_eq!(sfdp_page_size(&table([0, 0], 0x90)), 512);
+        assert_eq!(sfdp_page_size(&table([0, 0], 0)), DEFAULT_PAGE_SIZE);
+        assert_eq!(sfdp_page_size(&[0; 9]), DEFAULT_PAGE_SIZE);
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::{
+    BusPirate, Error,
+    bpio::{self, DataRequest},
+    buspirate::POLL_INTERVAL,
+    modes::HdUart,
+    util::chunk_ranges,
+};
+
+impl BusPirate<HdUart> {
+    /// Set the delay between the end of a transmission and the start of
+    /// reception.
+    pub fn set_turnaround(&mut self, turnaround: Duration) {
+        self.mode.turnaround = turnaround;
+    }
+
+    /// Set how long to wait for a complete response after the turnaround delay.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.mode.timeout = timeout;
+    }
+
+    /// Transmit `write`, then receive into `read`.
+    ///
+    /// With no turnaround delay, the end of the transmission and the start of
+    /// reception are sent to the Bus Pirate in one request, so the firmware
+    /// turns the line around as soon as the last byte has been sent. Otherwise
+    /// reception starts once the delay has passed, timed on the host from the
+    /// end of the last write request. The response is polled for until `read`
+    /// is full or the timeout expires. Returns the number of bytes received,
+    /// which is less than `read.len()` if the timeout expired first.
+    ///
+    /// On a single-wire bus the Bus Pirate's receiver also sees the bytes it
+    /// transmits, so they are received ahead of the response. Make `read`
+    /// long enough to hold them too, and skip the first `write.len()` bytes.
+    pub fn transaction(&mut self, write: &[u8], read: &mut [u8]) -> Result<usize, Error> {
+        debug!("HDUART Transaction w:{} r:{}", write.len(), read.len());
+
+        if !self.mode.turnaround.is_zero() {
+            self.write(write)?;
+            std::thread::sleep(self.mode.turnaround);
+            return self.receive(read);
+        }
+
+        // Writes too long for one request are sent ahead in chunks.
+        let chunks: Vec<_> = chunk_ranges(write.len(), self.limits.max_write).collect();
+        let (last, earlier) = chunks
+            .split_last()
+            .expect("chunk_ranges yields at least one range");
+        for range in earlier {
+            self.write_bytes(&write[range.clone()])?;
+        }
+
+        let read_len = read.len().min(self.limits.max_read);
+        let request = DataRequest::builder()
+            .start(false)
+            .stop(false)
+            .bytes_to_write(&write[last.clone()])
+            .bytes_to_read(read_len)
+            .build();
+        let received = self.send_data_request(request)?;
+        let data = bpio::check_received_up_to(received, read_len)?;
+        read[..data.len()].copy_from_slice(&data);
+
+        let received = data.len();
+        Ok(received + self.receive(&mut read[received..])?)
+    }
+
+    /// Transmit `write` without waiting for a response.
+    pub fn write(&mut self, write: &[u8]) -> Result<(), Error> {
+        debug!("HDUART Write w:{}", write.len());
+        for range in chunk_ranges(write.len(), self.limits.max_write) {
+            self.write_bytes(&write[range])?;
+        }
+        Ok(())
+    }
+
+    /// Receive into `read` until it is full or the timeout expires.
+    ///
+    /// Returns the number of bytes received.
+    pub fn read(&mut self, read: &mut [u8]) -> Result<usize, Error> {
+        debug!("HDUART Read r:{}", read.len());
+        self.receive(read)
+    }
+
+    fn receive(&mut self, read: &mut [u8]) -> Result<usize, Error> {
+        let deadline = Instant::now() + self.mode.timeout;
+        let mut received = 0;
+        while received < read.len() {
+            match self.read_available(&mut read[received..])? {
+                0 if Instant::now() >= deadline => break,
+                0 => std::thread::sleep(POLL_INTERVAL),
+                n => received += n,
+            }
+        }
+        Ok(received)
+    }
+}
@@ -0,0 +1,374 @@
+//! Infrared transmit modulation, with NEC and RC5 encoding.
+//!
+//! IR signals are represented as sequences of durations in microseconds,
+//! alternating between mark (carrier on) and space (carrier off), starting
+//! with a mark. [`Nec`] and [`Rc5`] frames encode to and decode from them.
+//!
+//! Raw transmit and capture are not offered yet. The BPIO2 schema carries
+//! infrared data as plain bytes without saying how timings are packed into
+//! them, nor which receiver each `rx_sensor` number selects, and neither has
+//! been confirmed against the firmware's infrared mode. Entering the mode
+//! sets only the transmit carrier and leaves the firmware's default receiver.
+
+/// Carrier frequency used to modulate transmitted marks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CarrierFrequency(u32);
+
+impl CarrierFrequency {
+    pub const KHZ_36: Self = Self(36_000);
+    /// Used by NEC and most other consumer remotes.
+    pub const KHZ_38: Self = Self(38_000);
+    pub const KHZ_40: Self = Self(40_000);
+    pub const KHZ_56: Self = Self(56_000);
+
+    pub const fn from_hz(hz: u32) -> Self {
+        Self(hz)
+    }
+
+    pub const fn hz(self) -> u32 {
+        self.0
+    }
+}
+
+/// Returns `true` if `actual` is within 25% of `expected`.
+fn within_tolerance(actual: u16, expected: u16) -> bool {
+    let (actual, expected) = (u32::from(actual), u32::from(expected));
+    actual * 4 >= expected * 3 && actual * 4 <= expected * 5
+}
+
+/// An NEC remote control frame.
+///
+/// The original format has an 8-bit address, sent followed by its inverse.
+/// The extended format uses those two bytes for a 16-bit address instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nec {
+    address: u16,
+    extended: bool,
+    command: u8,
+}
+
+impl Nec {
+    const LEADER_MARK: u16 = 9_000;
+    const LEADER_SPACE: u16 = 4_500;
+    const BIT_MARK: u16 = 562;
+    const ZERO_SPACE: u16 = 562;
+    const ONE_SPACE: u16 = 1_687;
+
+    /// A frame in the original format, with an 8-bit address.
+    pub fn new(address: u8, command: u8) -> Self {
+        Self {
+            address: u16::from(address),
+            extended: false,
+            command,
+        }
+    }
+
+    /// A frame in the extended format, with a 16-bit address.
+    ///
+    /// Returns `None` if the high byte of `address` is the inverse of the low
+    /// byte, as the frame would then be sent exactly as the original format
+    /// frame for the low byte, and decode as one.
+    pub fn extended(address: u16, command: u8) -> Option<Self> {
+        let [low, high] = address.to_le_bytes();
+        (high != !low).then_some(Self {
+            address,
+            extended: true,
+            command,
+        })
+    }
+
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// Returns `true` for a frame in the extended format.
+    pub fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    pub fn command(&self) -> u8 {
+        self.command
+    }
+
+    fn bytes(&self) -> [u8; 4] {
+        let [address_low, address_high] = if self.extended {
+            self.address.to_le_bytes()
+        } else {
+            [self.address as u8, !(self.address as u8)]
+        };
+        [address_low, address_high, self.command, !self.command]
+    }
+
+    /// Encode as raw mark/space timings.
+    pub fn encode(&self) -> Vec<u16> {
+        let mut timings = vec![Self::LEADER_MARK, Self::LEADER_SPACE];
+        for byte in self.bytes() {
+            // Least significant bit first.
+            for bit in 0..8 {
+                let space = if byte & (1 << bit) != 0 {
+                    Self::ONE_SPACE
+                } else {
+                    Self::ZERO_SPACE
+                };
+                timings.extend([Self::BIT_MARK, space]);
+            }
+        }
+        // Trailing mark to terminate the final space.
+        timings.push(Self::BIT_MARK);
+        timings
+    }
+
+    /// Decode raw mark/space timings, returning `None` if they are not a valid
+    /// NEC frame.
+    pub fn decode(timings: &[u16]) -> Option<Self> {
+        let [leader_mark, leader_space, rest @ ..] = timings else {
+            return None;
+        };
+        if !within_tolerance(*leader_mark, Self::LEADER_MARK)
+            || !within_tolerance(*leader_space, Self::LEADER_SPACE)
+        {
+            return None;
+        }
+
+        let mut bytes = [0u8; 4];
+        let mut pairs = rest.chunks_exact(2);
+        for bit_index in 0..32 {
+            let [mark, space] = *pairs.next()? else {
+                return None;
+            };
+            if !within_tolerance(mark, Self::BIT_MARK) {
+                return None;
+            }
+            if within_tolerance(space, Self::ONE_SPACE) {
+                bytes[bit_index / 8] |= 1 << (bit_index % 8);
+            } else if !within_tolerance(space, Self::ZERO_SPACE) {
+                return None;
+            }
+        }
+
+        let [address_low, address_high, command, command_inverse] = bytes;
+        if command != !command_inverse {
+            return None;
+        }
+        if address_low == !address_high {
+            Some(Self::new(address_low, command))
+        } else {
+            Self::extended(u16::from_le_bytes([address_low, address_high]), command)
+        }
+    }
+}
+
+/// A Philips RC5 remote control frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rc5 {
+    address: u8,
+    command: u8,
+    toggle: bool,
+}
+
+impl Rc5 {
+    const HALF_BIT: u16 = 889;
+
+    /// A frame with a 5-bit `address` and a 7-bit `command`; higher bits are
+    /// ignored. `toggle` is flipped by the remote each time a key is pressed
+    /// anew.
+    pub fn new(address: u8, command: u8, toggle: bool) -> Self {
+        Self {
+            address: address & 0x1F,
+            command: command & 0x7F,
+            toggle,
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// The 7-bit command. The top bit is carried in the inverted field bit.
+    pub fn command(&self) -> u8 {
+        self.command
+    }
+
+    /// Returns `true` if the toggle bit is set.
+    pub fn toggle(&self) -> bool {
+        self.toggle
+    }
+
+    /// The frame's 14 bits, most significant (the first start bit) first.
+    fn bits(&self) -> u16 {
+        let field = self.command & 0x40 == 0;
+        (1 << 13)
+            | (u16::from(field) << 12)
+            | (u16::from(self.toggle) << 11)
+            | (u16::from(self.address) << 6)
+            | u16::from(self.command & 0x3F)
+    }
+
+    /// Encode as raw mark/space timings.
+    pub fn encode(&self) -> Vec<u16> {
+        // Manchester encoding: a one is a space then a mark, a zero is a mark
+        // then a space. Adjacent half-bits at the same level merge.
+        let bits = self.bits();
+        let halves = (0..14).rev().flat_map(|i| {
+            if bits & (1 << i) != 0 {
+                [false, true]
+            } else {
+                [true, false]
+            }
+        });
+
+        let mut timings: Vec<u16> = Vec::new();
+        let mut level = false;
+        for half in halves {
+            if half == level {
+                if let Some(last) = timings.last_mut() {
+                    *last += Self::HALF_BIT;
+                }
+            } else {
+                timings.push(Self::HALF_BIT);
+                level = half;
+            }
+        }
+        // The leading space of the first start bit is indistinguishable from
+        // idle, so the timings begin with a mark. Drop any trailing space too.
+        if timings.len().is_multiple_of(2) {
+            timings.pop();
+        }
+        timings
+    }
+
+    /// Decode raw mark/space timings, returning `None` if they are not a valid
+    /// RC5 frame.
+    pub fn decode(timings: &[u16]) -> Option<Self> {
+        // Reconstruct the half-bit levels, starting with the space of the first
+        // start bit that is lost in the idle period.
+        let mut halves = vec![false];
+        for (i, &duration) in timings.iter().enumerate() {
+            let mark = i.is_multiple_of(2);
+            let count = if within_tolerance(duration, Self::HALF_BIT) {
+                1
+            } else if within_tolerance(duration, 2 * Self::HALF_BIT) {
+                2
+            } else {
+                return None;
+            };
+            halves.extend(std::iter::repeat_n(mark, count));
+        }
+        // A final zero bit ends with a space that merges into the idle period.
+        if !halves.len().is_multiple_of(2) {
+            halves.push(false);
+        }
+        if halves.len() != 28 {
+            return None;
+        }
+
+        let mut bits = 0u16;
+        for pair in halves.chunks_exact(2) {
+            let bit = match pair {
+                [false, true] => 1,
+                [true, false] => 0,
+                _ => return None,
+            };
+            bits = (bits << 1) | bit;
+        }
+
+        let mut command = (bits & 0x3F) as u8;
+        if bits & (1 << 12) == 0 {
+            // An inverted field bit of zero means the command's top bit is set.
+            command |= 0x40;
+        }
+        Some(Self::new(
+            ((bits >> 6) & 0x1F) as u8,
+            command,
+            bits & (1 << 11) != 0,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stretch every timing by `percent`, as a slow or fast remote would.
+    fn scale(timings: &[u16], percent: u32) -> Vec<u16> {
+        timings
+            .iter()
+            .map(|&t| (u32::from(t) * percent / 100) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn nec_round_trip() {
+        let frames = [
+            Nec::new(0x00, 0x00),
+            Nec::new(0x04, 0x08),
+            Nec::new(0xFF, 0xFF),
+            Nec::extended(0x1234, 0x56).unwrap(),
+            Nec::extended(0x0100, 0x01).unwrap(),
+            Nec::extended(0xED13, 0x80).unwrap(),
+        ];
+        for frame in frames {
+            let timings = frame.encode();
+            assert_eq!(timings.len(), 2 + 64 + 1);
+            assert_eq!(Nec::decode(&timings), Some(frame));
+            assert_eq!(Nec::decode(&scale(&timings, 90)), Some(frame));
+            assert_eq!(Nec::decode(&scale(&timings, 110)), Some(frame));
+        }
+    }
+
+    #[test]
+    fn nec_standard_address_is_sent_with_its_inverse() {
+        let timings = Nec::new(0x12, 0x34).encode();
+        let frame = Nec::decode(&timings).unwrap();
+        assert!(!frame.is_extended());
+        assert_eq!(frame.address(), 0x12);
+        assert_eq!(frame.command(), 0x34);
+    }
+
+    #[test]
+    fn nec_extended_address_like_standard_is_rejected() {
+        assert_eq!(Nec::extended(0xED12, 0x00), None);
+        assert!(Nec::extended(0xED12 ^ 1, 0x00).is_some());
+    }
+
+    #[test]
+    fn nec_decode_rejects_bad_frames() {
+        let timings = Nec::new(0x12, 0x34).encode();
+        assert_eq!(Nec::decode(&timings[..timings.len() - 3]), None);
+        assert_eq!(Nec::decode(&scale(&timings, 140)), None);
+
+        // Flip the first command bit without flipping its inverse.
+        let mut corrupt = timings.clone();
+        let space = 2 + 2 * 16 + 1;
+        corrupt[space] = if corrupt[space] == Nec::ONE_SPACE {
+            Nec::ZERO_SPACE
+        } else {
+            Nec::ONE_SPACE
+        };
+        assert_eq!(Nec::decode(&corrupt), None);
+    }
+
+    #[test]
+    fn rc5_round_trip() {
+        for address in [0x00, 0x05, 0x1F] {
+            for command in [0x00, 0x01, 0x3F, 0x40, 0x7F] {
+                for toggle in [false, true] {
+                    let frame = Rc5::new(address, command, toggle);
+                    let timings = frame.encode();
+                    assert!(!timings.len().is_multiple_of(2), "must end with a mark");
+                    assert_eq!(Rc5::decode(&timings), Some(frame));
+                    assert_eq!(Rc5::decode(&scale(&timings, 85)), Some(frame));
+                    assert_eq!(Rc5::decode(&scale(&timings, 115)), Some(frame));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rc5_decode_rejects_bad_frames() {
+        let timings = Rc5::new(0x05, 0x35, false).encode();
+        assert_eq!(Rc5::decode(&timings[..timings.len() - 2]), None);
+        assert_eq!(Rc5::decode(&scale(&timings, 50)), None);
+        assert_eq!(Rc5::decode(&[]), None);
+    }
+}
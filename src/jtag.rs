@@ -0,0 +1,414 @@
+//! JTAG TAP control and chain scanning.
+//!
+//! The firmware's JTAG mode is a pin finder and does not expose shifting over
+//! BPIO2, so JTAG is bit-banged over the IO pins in DIO mode. Each TCK cycle
+//! takes two or three round trips, which is slow but plenty for bring-up checks
+//! such as reading IDCODEs.
+
+use std::collections::VecDeque;
+
+use log::debug;
+
+use crate::{
+    BusPirate, Configuration, Error, IoConfig, IoDirection, LogicLevel, StatusQuery, modes::Jtag,
+};
+
+/// Assignment of the JTAG signals to IO pins.
+#[derive(Debug, Clone, Copy)]
+pub struct JtagPins {
+    pub tck: usize,
+    pub tms: usize,
+    pub tdi: usize,
+    pub tdo: usize,
+}
+
+impl JtagPins {
+    /// Check that every signal is on its own pin in the range `0..8`.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let pins = [self.tck, self.tms, self.tdi, self.tdo];
+        for (i, &pin) in pins.iter().enumerate() {
+            if pin >= 8 || pins[..i].contains(&pin) {
+                return Err(Error::InvalidPin { pin });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for JtagPins {
+    /// TCK on IO0, TMS on IO1, TDI on IO2 and TDO on IO3.
+    fn default() -> Self {
+        Self {
+            tck: 0,
+            tms: 1,
+            tdi: 2,
+            tdo: 3,
+        }
+    }
+}
+
+/// States of the JTAG TAP controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapState {
+    TestLogicReset,
+    RunTestIdle,
+    SelectDrScan,
+    CaptureDr,
+    ShiftDr,
+    Exit1Dr,
+    PauseDr,
+    Exit2Dr,
+    UpdateDr,
+    SelectIrScan,
+    CaptureIr,
+    ShiftIr,
+    Exit1Ir,
+    PauseIr,
+    Exit2Ir,
+    UpdateIr,
+}
+
+impl TapState {
+    const ALL: [TapState; 16] = [
+        TapState::TestLogicReset,
+        TapState::RunTestIdle,
+        TapState::SelectDrScan,
+        TapState::CaptureDr,
+        TapState::ShiftDr,
+        TapState::Exit1Dr,
+        TapState::PauseDr,
+        TapState::Exit2Dr,
+        TapState::UpdateDr,
+        TapState::SelectIrScan,
+        TapState::CaptureIr,
+        TapState::ShiftIr,
+        TapState::Exit1Ir,
+        TapState::PauseIr,
+        TapState::Exit2Ir,
+        TapState::UpdateIr,
+    ];
+
+    /// The state entered on a TCK rising edge with the given TMS level.
+    pub fn next(self, tms: bool) -> TapState {
+        use TapState::*;
+        match (self, tms) {
+            (TestLogicReset, false) => RunTestIdle,
+            (TestLogicReset, true) => TestLogicReset,
+            (RunTestIdle, false) => RunTestIdle,
+            (RunTestIdle, true) => SelectDrScan,
+            (SelectDrScan, false) => CaptureDr,
+            (SelectDrScan, true) => SelectIrScan,
+            (CaptureDr, false) => ShiftDr,
+            (CaptureDr, true) => Exit1Dr,
+            (ShiftDr, false) => ShiftDr,
+            (ShiftDr, true) => Exit1Dr,
+            (Exit1Dr, false) => PauseDr,
+            (Exit1Dr, true) => UpdateDr,
+            (PauseDr, false) => PauseDr,
+            (PauseDr, true) => Exit2Dr,
+            (Exit2Dr, false) => ShiftDr,
+            (Exit2Dr, true) => UpdateDr,
+            (UpdateDr, false) => RunTestIdle,
+            (UpdateDr, true) => SelectDrScan,
+            (SelectIrScan, false) => CaptureIr,
+            (SelectIrScan, true) => TestLogicReset,
+            (CaptureIr, false) => ShiftIr,
+            (CaptureIr, true) => Exit1Ir,
+            (ShiftIr, false) => ShiftIr,
+            (ShiftIr, true) => Exit1Ir,
+            (Exit1Ir, false) => PauseIr,
+            (Exit1Ir, true) => UpdateIr,
+            (PauseIr, false) => PauseIr,
+            (PauseIr, true) => Exit2Ir,
+            (Exit2Ir, false) => ShiftIr,
+            (Exit2Ir, true) => UpdateIr,
+            (UpdateIr, false) => RunTestIdle,
+            (UpdateIr, true) => SelectDrScan,
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|&s| s == self)
+            .expect("All states are listed.")
+    }
+
+    /// The shortest TMS sequence that moves from `self` to `target`.
+    pub fn path_to(self, target: TapState) -> Vec<bool> {
+        // Breadth-first search, recording how each state was first reached.
+        let mut reached_by: [Option<(TapState, bool)>; 16] = [None; 16];
+        let mut queue = VecDeque::from([self]);
+        while let Some(state) = queue.pop_front() {
+            if state == target {
+                break;
+            }
+            for tms in [false, true] {
+                let next = state.next(tms);
+                if next != self && reached_by[next.index()].is_none() {
+                    reached_by[next.index()] = Some((state, tms));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        let mut path = Vec::new();
+        let mut state = target;
+        while state != self {
+            let (previous, tms) = reached_by[state.index()].expect("All states are reachable.");
+            path.push(tms);
+            state = previous;
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// A device identification code read from a JTAG chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdCode(pub u32);
+
+impl IdCode {
+    pub fn version(&self) -> u8 {
+        (self.0 >> 28) as u8
+    }
+
+    pub fn part_number(&self) -> u16 {
+        (self.0 >> 12) as u16
+    }
+
+    /// JEP106 manufacturer identity, as the continuation code bank and the ID
+    /// within that bank.
+    pub fn manufacturer(&self) -> (u8, u8) {
+        let bank = ((self.0 >> 8) & 0x0F) as u8;
+        let id = ((self.0 >> 1) & 0x7F) as u8;
+        (bank, id)
+    }
+}
+
+impl std::fmt::Display for IdCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#010X}", self.0)
+    }
+}
+
+/// Upper bound on the devices read by [`BusPirate::scan_chain`]. A chain that
+/// has not ended by then has TDO stuck low.
+const MAX_CHAIN_LENGTH: usize = 32;
+
+fn level(high: bool) -> LogicLevel {
+    if high {
+        LogicLevel::High
+    } else {
+        LogicLevel::Low
+    }
+}
+
+impl BusPirate<Jtag> {
+    /// Configure the pin directions and reset the TAP controller.
+    pub(crate) fn jtag_init(&mut self) -> Result<(), Error> {
+        let pins = self.mode.pins;
+        let mut io = IoConfig::new();
+        for pin in [pins.tck, pins.tms, pins.tdi] {
+            io.set_direction(pin, IoDirection::Output);
+        }
+        io.set_direction(pins.tdo, IoDirection::Input);
+        io.set_level(pins.tck, LogicLevel::Low);
+        io.set_level(pins.tms, LogicLevel::High);
+        self.configure(Configuration::builder().io(io).build())?;
+        self.reset_tap()
+    }
+
+    /// The host's view of the TAP controller state.
+    pub fn tap_state(&self) -> TapState {
+        self.mode.state
+    }
+
+    /// Perform one TCK cycle, returning TDO as sampled before the rising edge.
+    ///
+    /// TDO is only read if `capture` is set; otherwise `false` is returned.
+    fn clock(&mut self, tms: bool, tdi: bool, capture: bool) -> Result<bool, Error> {
+        let pins = self.mode.pins;
+
+        let mut io = IoConfig::new();
+        io.set_level(pins.tck, LogicLevel::Low);
+        io.set_level(pins.tms, level(tms));
+        io.set_level(pins.tdi, level(tdi));
+        self.configure(Configuration::builder().io(io).build())?;
+
+        let tdo = capture && self.status(&[StatusQuery::Io])?.io_value & (1 << pins.tdo) != 0;
+
+        let mut io = IoConfig::new();
+        io.set_level(pins.tck, LogicLevel::High);
+        self.configure(Configuration::builder().io(io).build())?;
+
+        self.mode.state = self.mode.state.next(tms);
+        Ok(tdo)
+    }
+
+    /// Force the TAP controller into Test-Logic-Reset, whatever its state.
+    pub fn reset_tap(&mut self) -> Result<(), Error> {
+        debug!("JTAG: Reset");
+        for _ in 0..5 {
+            self.clock(true, false, false)?;
+        }
+        self.mode.state = TapState::TestLogicReset;
+        Ok(())
+    }
+
+    /// Move the TAP controller to `target` by the shortest path.
+    pub fn goto_state(&mut self, target: TapState) -> Result<(), Error> {
+        for tms in self.mode.state.path_to(target) {
+            self.clock(tms, false, false)?;
+        }
+        Ok(())
+    }
+
+    /// Shift `tdi` through the instruction register, least significant bit
+    /// first, returning the bits shifted out. Ends in Run-Test/Idle.
+    pub fn shift_ir(&mut self, tdi: &[bool]) -> Result<Vec<bool>, Error> {
+        self.shift(TapState::ShiftIr, tdi)
+    }
+
+    /// Shift `tdi` through the selected data register, least significant bit
+    /// first, returning the bits shifted out. Ends in Run-Test/Idle.
+    pub fn shift_dr(&mut self, tdi: &[bool]) -> Result<Vec<bool>, Error> {
+        self.shift(TapState::ShiftDr, tdi)
+    }
+
+    /// Shift bits in a Shift-IR or Shift-DR state, leaving on the last bit.
+    fn shift(&mut self, state: TapState, tdi: &[bool]) -> Result<Vec<bool>, Error> {
+        debug!("JTAG: Shift {} bits in {state:?}", tdi.len());
+        if tdi.is_empty() {
+            // Every cycle in a shift state shifts a bit, including the one that
+            // leaves it, so there is no way through without shifting.
+            self.goto_state(TapState::RunTestIdle)?;
+            return Ok(Vec::new());
+        }
+        self.goto_state(state)?;
+        let mut tdo = Vec::with_capacity(tdi.len());
+        for (i, &bit) in tdi.iter().enumerate() {
+            let last = i + 1 == tdi.len();
+            tdo.push(self.clock(last, bit, true)?);
+        }
+        self.goto_state(TapState::RunTestIdle)?;
+        Ok(tdo)
+    }
+
+    /// Read the IDCODE of every device in the chain, nearest TDO first.
+    ///
+    /// After a reset, devices with an IDCODE register select it, and devices
+    /// without one select their 1-bit bypass register. Bypassed devices are
+    /// reported as `None`.
+    ///
+    /// Fails with [`Error::BusError`] if the end of the chain is not found,
+    /// which happens when TDO is stuck low.
+    pub fn scan_chain(&mut self) -> Result<Vec<Option<IdCode>>, Error> {
+        self.reset_tap()?;
+        self.goto_state(TapState::ShiftDr)?;
+
+        let mut devices = Vec::new();
+        let mut ended = false;
+        while devices.len() < MAX_CHAIN_LENGTH {
+            // Shift in ones, so that reading all ones marks the end of the chain.
+            if !self.clock(false, true, true)? {
+                // IDCODEs always start with a one, so this is a bypass register.
+                devices.push(None);
+                continue;
+            }
+            let mut id = 1u32;
+            for bit in 1..32 {
+                if self.clock(false, true, true)? {
+                    id |= 1 << bit;
+                }
+            }
+            if id == u32::MAX {
+                ended = true;
+                break;
+            }
+            debug!("JTAG: found IDCODE {id:#010X}");
+            devices.push(Some(IdCode(id)));
+        }
+
+        // Leave Shift-DR and return to idle.
+        self.clock(true, true, false)?;
+        self.goto_state(TapState::RunTestIdle)?;
+        if !ended {
+            debug!("JTAG: no end of chain after {MAX_CHAIN_LENGTH} devices");
+            return Err(Error::BusError);
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(from: TapState, tms: &[bool]) -> TapState {
+        tms.iter().fold(from, |state, &tms| state.next(tms))
+    }
+
+    #[test]
+    fn five_tms_high_cycles_reset_from_any_state() {
+        for state in TapState::ALL {
+            assert_eq!(walk(state, &[true; 5]), TapState::TestLogicReset);
+        }
+    }
+
+    #[test]
+    fn stable_states_hold_with_tms_low() {
+        use TapState::*;
+        for state in [RunTestIdle, ShiftDr, PauseDr, ShiftIr, PauseIr] {
+            assert_eq!(state.next(false), state);
+        }
+        assert_eq!(TestLogicReset.next(true), TestLogicReset);
+    }
+
+    #[test]
+    fn path_to_reaches_every_state() {
+        for from in TapState::ALL {
+            for to in TapState::ALL {
+                let path = from.path_to(to);
+                assert_eq!(walk(from, &path), to, "{from:?} -> {to:?}");
+                if from == to {
+                    assert!(path.is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn path_to_is_shortest() {
+        use TapState::*;
+        assert_eq!(TestLogicReset.path_to(RunTestIdle), [false]);
+        assert_eq!(TestLogicReset.path_to(ShiftDr), [false, true, false, false]);
+        assert_eq!(RunTestIdle.path_to(ShiftIr), [true, true, false, false]);
+        assert_eq!(ShiftDr.path_to(RunTestIdle), [true, true, false]);
+        assert_eq!(ShiftIr.path_to(ShiftDr), [true, true, true, false, false]);
+        assert_eq!(Exit1Dr.path_to(TestLogicReset), [true; 4]);
+    }
+
+    #[test]
+    fn pins_must_be_distinct_and_in_range() {
+        assert!(JtagPins::default().validate().is_ok());
+
+        let out_of_range = JtagPins {
+            tdo: 8,
+            ..JtagPins::default()
+        };
+        assert!(matches!(
+            out_of_range.validate(),
+            Err(Error::InvalidPin { pin: 8 })
+        ));
+
+        let shared = JtagPins {
+            tdi: 0,
+            ..JtagPins::default()
+        };
+        assert!(matches!(
+            shared.validate(),
+            Err(Error::InvalidPin { pin: 0 })
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+use log::debug;
+
+use crate::{BusPirate, Error, bpio::DataRequest, modes::Led, util::chunk_ranges};
+
+/// Family of addressable LEDs driven in LED mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedType {
+    /// WS2812, SK6812 and compatible single-wire LEDs.
+    Ws2812,
+    /// APA102, SK9822 and compatible clocked LEDs.
+    Apa102,
+    /// The Bus Pirate's own LEDs.
+    Onboard,
+}
+
+impl LedType {
+    /// The BPIO2 submode number, an index into the LED mode's device list in
+    /// the Bus Pirate 5 firmware (`src/mode/hwled.c` in
+    /// DangerousPrototypes/BusPirate5-firmware), which is the order the
+    /// terminal's LED mode setup menu offers them in.
+    pub(crate) fn for_bpio(self) -> u8 {
+        match self {
+            LedType::Ws2812 => 0,
+            LedType::Apa102 => 1,
+            LedType::Onboard => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl From<(u8, u8, u8)> for Rgb {
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// Pack colors into the wire format for the LED family.
+fn pack(led_type: LedType, brightness: u8, colors: &[Rgb]) -> Vec<u8> {
+    match led_type {
+        // Green, red, blue.
+        LedType::Ws2812 | LedType::Onboard => colors.iter().flat_map(|c| [c.g, c.r, c.b]).collect(),
+        // Header byte of three set bits and a 5-bit brightness, then blue, green, red.
+        LedType::Apa102 => colors
+            .iter()
+            .flat_map(|c| [0xE0 | brightness, c.b, c.g, c.r])
+            .collect(),
+    }
+}
+
+impl BusPirate<Led> {
+    /// Set the global brightness used for APA102 LEDs, from 0 to 31.
+    ///
+    /// Values above 31 are clamped. This has no effect on other LED types.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.mode.brightness = brightness.min(31);
+    }
+
+    /// Send one color per LED, starting with the LED closest to the Bus Pirate.
+    ///
+    /// The start and end of the frame (the reset period or start/end frames,
+    /// depending on the LED type) are generated by the Bus Pirate.
+    ///
+    /// APA102 frames too large for one request are split across several, which
+    /// is safe because the LEDs are clocked and ignore pauses in the clock.
+    /// WS2812 and onboard LEDs latch their colors whenever the data line stays
+    /// low for longer than the reset period, a few hundred microseconds at
+    /// most. The pause between two requests is a USB round trip, which is
+    /// usually longer than that, so a split frame would latch part way through
+    /// and the rest of the colors would land on the first LEDs of the strip.
+    /// Those frames must fit in one request, and longer ones fail with
+    /// [`Error::Unsupported`] before anything is sent.
+    pub fn write_colors(&mut self, colors: &[Rgb]) -> Result<(), Error> {
+        debug!("LED Write {} x {:?}", colors.len(), self.mode.led_type);
+
+        let data = pack(self.mode.led_type, self.mode.brightness, colors);
+        if self.mode.led_type != LedType::Apa102 && data.len() > self.limits.max_write {
+            return Err(Error::Unsupported(
+                "WS2812 frame longer than one request, which would latch part way",
+            ));
+        }
+        let chunks: Vec<_> = chunk_ranges(data.len(), self.limits.max_write).collect();
+        let last = chunks.len() - 1;
+        for (i, range) in chunks.into_iter().enumerate() {
+            let request = DataRequest::builder()
+                .start(i == 0)
+                .stop(i == last)
+                .bytes_to_write(&data[range])
+                .build();
+            self.send_data_request(request)?;
+        }
+        Ok(())
+    }
+}
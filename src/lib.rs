@@ -1,16 +1,42 @@
 mod bpio;
 mod buspirate;
+mod discovery;
+mod eh_digital;
 mod eh_i2c;
 mod eh_spi;
+mod eh_uart;
 mod error;
+mod hduart;
+mod led;
+mod rawwire;
+mod spi;
+mod transport;
 mod util;
 
+pub mod ds2482;
+pub mod eeprom;
+pub mod emulator;
+pub mod flash;
+pub mod infrared;
+pub mod jtag;
 pub mod modes;
+pub mod onewire;
+pub mod recording;
 
 use util::{EncodedRequest, Response};
 
-pub use util::{ChipSelectPolarity, ClockPhase, ClockPolarity};
+pub use util::{
+    ChipSelectPolarity, ClockPhase, ClockPolarity, DataBits, FlowControl, HdUartConfig, Parity,
+    SignalPolarity, StopBits, UartConfig,
+};
 
-pub use bpio::{BitOrder, Configuration, IoDirection, LogicLevel, ModeConfiguration, PsuConfig};
+pub use bpio::{
+    BitOrder, BpioVersion, Configuration, IoConfig, IoDirection, LogicLevel, ModeConfiguration,
+    PsuConfig, Status, StatusQuery,
+};
 pub use buspirate::{open, BusPirate};
+pub use discovery::{BusPirateInfo, discover};
+pub use eh_digital::Pin;
 pub use error::Error;
+pub use led::{LedType, Rgb};
+pub use transport::Transport;
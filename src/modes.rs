@@ -1,3 +1,8 @@
+use std::time::Duration;
+
+use crate::LedType;
+use crate::jtag::{JtagPins, TapState};
+
 mod sealed {
     pub trait Sealed {}
 }
@@ -26,19 +31,101 @@ impl_mode!(I2c);
 pub struct Spi;
 impl_mode!(Spi);
 
-#[derive(Debug, Clone, Copy)]
+pub struct Uart;
+impl_mode!(Uart);
+
+pub struct HdUart {
+    /// Delay between the end of a transmission and the start of reception.
+    pub(crate) turnaround: Duration,
+    /// How long to wait for a complete response after the turnaround delay.
+    pub(crate) timeout: Duration,
+}
+impl_mode!(HdUart);
+
+pub struct OneWire;
+impl_mode!(OneWire);
+
+pub struct TwoWire;
+impl_mode!(TwoWire);
+
+pub struct ThreeWire;
+impl_mode!(ThreeWire);
+
+pub struct Dio {
+    /// Levels last set by the host for each pin, as a bitmask.
+    pub(crate) driven: u8,
+}
+impl_mode!(Dio);
+
+pub struct Led {
+    pub(crate) led_type: LedType,
+    /// APA102 global brightness, from 0 to 31.
+    pub(crate) brightness: u8,
+}
+impl_mode!(Led);
+
+pub struct Infrared;
+impl_mode!(Infrared);
+
+/// JTAG, bit-banged over the IO pins in DIO mode.
+pub struct Jtag {
+    pub(crate) pins: JtagPins,
+    /// Host-side tracking of the TAP controller state.
+    pub(crate) state: TapState,
+}
+impl_mode!(Jtag);
+
+/// Modes that expose raw start/stop conditions and byte transfers.
+pub trait RawWireMode: ActiveMode {}
+impl RawWireMode for TwoWire {}
+impl RawWireMode for ThreeWire {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Modes {
     HiZ,
     I2c,
     Spi,
+    Uart,
+    HdUart,
+    OneWire,
+    TwoWire,
+    ThreeWire,
+    Dio,
+    Led,
+    Infrared,
+    Jtag,
 }
 
 impl Modes {
+    pub(crate) const ALL: [Modes; 12] = [
+        Modes::HiZ,
+        Modes::I2c,
+        Modes::Spi,
+        Modes::Uart,
+        Modes::HdUart,
+        Modes::OneWire,
+        Modes::TwoWire,
+        Modes::ThreeWire,
+        Modes::Dio,
+        Modes::Led,
+        Modes::Infrared,
+        Modes::Jtag,
+    ];
+
     pub(crate) fn name(&self) -> &'static str {
         match self {
             Modes::HiZ => "HiZ",
             Modes::I2c => "I2C",
             Modes::Spi => "SPI",
+            Modes::Uart => "UART",
+            Modes::HdUart => "HDUART",
+            Modes::OneWire => "1WIRE",
+            Modes::TwoWire => "2WIRE",
+            Modes::ThreeWire => "3WIRE",
+            Modes::Dio => "DIO",
+            Modes::Led => "LED",
+            Modes::Infrared => "INFRARED",
+            Modes::Jtag => "JTAG",
         }
     }
 }
@@ -50,17 +137,17 @@ impl std::str::FromStr for Modes {
         Ok(match s {
             "HiZ" => Self::HiZ,
             "I2C" => Self::I2c,
-            "1WIRE" => todo!("1WIRE"),
-            "UART" => todo!("UART"),
-            "HDUART" => todo!("HDUART"),
+            "1WIRE" => Self::OneWire,
+            "UART" => Self::Uart,
+            "HDUART" => Self::HdUart,
             "SPI" => Self::Spi,
-            "2WIRE" => todo!("2WIRE"),
-            "3WIRE" => todo!("3WIRE"),
-            "DIO" => todo!("DIO"),
-            "LED" => todo!("LED"),
-            "INFRARED" => todo!("INFRARED"),
-            "JTAG" => todo!("JTAG"),
-            other => todo!("unexpected mode {other:?}"),
+            "2WIRE" => Self::TwoWire,
+            "3WIRE" => Self::ThreeWire,
+            "DIO" => Self::Dio,
+            "LED" => Self::Led,
+            "INFRARED" => Self::Infrared,
+            "JTAG" => Self::Jtag,
+            other => return Err(crate::Error::InvalidMode(format!("unknown mode {other:?}"))),
         })
     }
 }
@@ -71,7 +158,36 @@ impl std::fmt::Display for Modes {
             Modes::HiZ => "HiZ",
             Modes::I2c => "I2C",
             Modes::Spi => "SPI",
+            Modes::Uart => "UART",
+            Modes::HdUart => "HDUART",
+            Modes::OneWire => "1WIRE",
+            Modes::TwoWire => "2WIRE",
+            Modes::ThreeWire => "3WIRE",
+            Modes::Dio => "DIO",
+            Modes::Led => "LED",
+            Modes::Infrared => "INFRARED",
+            Modes::Jtag => "JTAG",
         };
         write!(f, "{name}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parse_back_to_their_mode() {
+        for mode in Modes::ALL {
+            assert_eq!(mode.name().parse::<Modes>().unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn unknown_name_is_an_error() {
+        assert!(matches!(
+            "SWD".parse::<Modes>(),
+            Err(crate::Error::InvalidMode(_))
+        ));
+    }
+}
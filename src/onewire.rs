@@ -0,0 +1,262 @@
+//! 1-Wire bus operations, ROM commands and device search.
+//!
+//! ROM search is not available in the Bus Pirate's own 1-Wire mode. The search
+//! algorithm needs single-bit time slots, but BPIO2 1-Wire requests only
+//! transfer whole bytes and the firmware offers no search of its own, so
+//! [`BusPirate<OneWire>`] implements [`OneWireBus`] but not [`OneWireSearch`].
+//! Single-device buses can use [`OneWireBus::read_rom`] instead, and a
+//! [`Ds2482`](crate::ds2482::Ds2482) bridge on the I2C bus can search.
+
+use log::debug;
+
+use crate::{
+    BusPirate, Error,
+    bpio::{DataRequest, copy_received},
+    modes::OneWire,
+};
+
+/// ROM command: enumerate the ROM codes of all devices on the bus.
+pub const SEARCH_ROM: u8 = 0xF0;
+/// ROM command: read the ROM code of the only device on the bus.
+pub const READ_ROM: u8 = 0x33;
+/// ROM command: address a single device by its ROM code.
+pub const MATCH_ROM: u8 = 0x55;
+/// ROM command: address all devices on the bus at once.
+pub const SKIP_ROM: u8 = 0xCC;
+/// ROM command: enumerate only the devices with an alarm condition.
+pub const ALARM_SEARCH: u8 = 0xEC;
+
+/// Compute the Dallas/Maxim 1-Wire CRC8 (polynomial x^8 + x^5 + x^4 + 1).
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, &byte| {
+        let mut byte = byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 0x01;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8C;
+            }
+            byte >>= 1;
+        }
+        crc
+    })
+}
+
+/// 64-bit ROM code that uniquely identifies a 1-Wire device.
+///
+/// Bytes are in bus order: family code first, then the 48-bit serial number
+/// (least significant byte first), then the CRC8 of the preceding seven bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RomCode(pub [u8; 8]);
+
+impl RomCode {
+    pub fn family_code(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn serial_number(&self) -> [u8; 6] {
+        let mut serial = [0u8; 6];
+        serial.copy_from_slice(&self.0[1..7]);
+        serial
+    }
+
+    pub fn crc(&self) -> u8 {
+        self.0[7]
+    }
+
+    /// Returns `true` if the CRC byte matches the rest of the ROM code.
+    pub fn is_valid(&self) -> bool {
+        crc8(&self.0[..7]) == self.crc()
+    }
+}
+
+impl std::fmt::Display for RomCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Conventionally printed most significant byte (the CRC) first.
+        for byte in self.0.iter().rev() {
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Primitive operations on a 1-Wire bus, plus the standard ROM commands built
+/// on top of them.
+pub trait OneWireBus {
+    /// Issue a reset pulse, returning `true` if any device answered with a
+    /// presence pulse.
+    fn reset(&mut self) -> Result<bool, Error>;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error>;
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
+        self.write_bytes(&[byte])
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let mut buf = [0u8];
+        self.read_bytes(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Reset the bus and address every device at once.
+    ///
+    /// Returns `false` if no device is present.
+    fn skip_rom(&mut self) -> Result<bool, Error> {
+        if !self.reset()? {
+            return Ok(false);
+        }
+        self.write_byte(SKIP_ROM)?;
+        Ok(true)
+    }
+
+    /// Reset the bus and address the device with the given ROM code.
+    ///
+    /// Returns `false` if no device is present.
+    fn match_rom(&mut self, rom: &RomCode) -> Result<bool, Error> {
+        if !self.reset()? {
+            return Ok(false);
+        }
+        self.write_byte(MATCH_ROM)?;
+        self.write_bytes(&rom.0)?;
+        Ok(true)
+    }
+
+    /// Read the ROM code of the only device on the bus.
+    ///
+    /// Returns `None` if no device is present. If more than one device is
+    /// present their responses collide, which usually shows up as
+    /// [`Error::CrcMismatch`].
+    fn read_rom(&mut self) -> Result<Option<RomCode>, Error> {
+        if !self.reset()? {
+            return Ok(None);
+        }
+        self.write_byte(READ_ROM)?;
+        let mut rom = RomCode([0u8; 8]);
+        self.read_bytes(&mut rom.0)?;
+        if !rom.is_valid() {
+            return Err(Error::CrcMismatch);
+        }
+        Ok(Some(rom))
+    }
+}
+
+/// A 1-Wire bus that can also transfer single bits, and so search for
+/// devices.
+pub trait OneWireSearch: OneWireBus {
+    fn write_bit(&mut self, bit: bool) -> Result<(), Error>;
+
+    fn read_bit(&mut self) -> Result<bool, Error>;
+
+    /// Enumerate the ROM codes of all devices on the bus.
+    fn search(&mut self) -> Result<Vec<RomCode>, Error> {
+        search_with_command(self, SEARCH_ROM)
+    }
+
+    /// Enumerate the ROM codes of devices with an active alarm condition.
+    fn alarm_search(&mut self) -> Result<Vec<RomCode>, Error> {
+        search_with_command(self, ALARM_SEARCH)
+    }
+}
+
+/// The ROM search algorithm described in Maxim application note 187.
+fn search_with_command<B: OneWireSearch + ?Sized>(
+    bus: &mut B,
+    command: u8,
+) -> Result<Vec<RomCode>, Error> {
+    let mut found = Vec::new();
+    let mut rom = [0u8; 8];
+    // Bit positions are numbered 1 to 64; zero means no discrepancy.
+    let mut last_discrepancy = 0;
+
+    loop {
+        if !bus.reset()? {
+            break;
+        }
+        bus.write_byte(command)?;
+
+        let mut last_zero = 0;
+        for bit_number in 1..=64 {
+            let id_bit = bus.read_bit()?;
+            let complement_bit = bus.read_bit()?;
+            if id_bit && complement_bit {
+                // No devices are participating in the search.
+                return Ok(found);
+            }
+
+            let (byte, mask) = ((bit_number - 1) / 8, 1 << ((bit_number - 1) % 8));
+            let direction = if id_bit != complement_bit {
+                // All participating devices have the same bit here.
+                id_bit
+            } else {
+                // Discrepancy: take the same path as last time up to the last
+                // discrepancy, then the one-branch there, else the zero-branch.
+                let direction = if bit_number < last_discrepancy {
+                    rom[byte] & mask != 0
+                } else {
+                    bit_number == last_discrepancy
+                };
+                if !direction {
+                    last_zero = bit_number;
+                }
+                direction
+            };
+
+            if direction {
+                rom[byte] |= mask;
+            } else {
+                rom[byte] &= !mask;
+            }
+            bus.write_bit(direction)?;
+        }
+
+        let code = RomCode(rom);
+        if !code.is_valid() {
+            return Err(Error::CrcMismatch);
+        }
+        debug!("1-Wire: found {code}");
+        found.push(code);
+
+        last_discrepancy = last_zero;
+        if last_discrepancy == 0 {
+            // No untaken branches remain.
+            break;
+        }
+    }
+
+    Ok(found)
+}
+
+impl OneWireBus for BusPirate<OneWire> {
+    fn reset(&mut self) -> Result<bool, Error> {
+        debug!("1-Wire: Reset");
+        let request = DataRequest::builder().start(true).stop(false).build();
+        match self.send_data_request(request) {
+            Ok(_) => Ok(true),
+            // The firmware reports a missing presence pulse as an error message.
+            Err(Error::NoDevice) => {
+                debug!("1-Wire: no presence pulse");
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        debug!("1-Wire Write w:{}", bytes.len());
+        BusPirate::write_bytes(self, bytes)
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        debug!("1-Wire Read r:{}", buf.len());
+        let request = DataRequest::builder()
+            .start(false)
+            .stop(false)
+            .bytes_to_read(buf.len())
+            .build();
+        self.send_data_request(request)
+            .and_then(|received| copy_received(received, buf))
+    }
+}
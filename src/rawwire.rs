@@ -0,0 +1,114 @@
+//! Raw access to the generic 2-wire and 3-wire modes.
+//!
+//! These modes clock whole bytes in and out without imposing a protocol, for
+//! synchronous devices that are neither quite I2C nor SPI.
+//!
+//! Bit-level clocking is not available. BPIO2 data requests carry whole bytes
+//! and the firmware has no request for single clock pulses or bits, so
+//! protocols with odd-length frames cannot be driven from here. Devices that
+//! send bytes least significant bit first, such as the TM1637, can be handled
+//! by setting [`BitOrder::Lsb`](crate::BitOrder::Lsb) when entering the mode.
+
+use log::debug;
+
+use crate::{
+    BusPirate, Error,
+    bpio::{DataRequest, copy_received},
+    modes::RawWireMode,
+    util::chunk_ranges,
+};
+
+impl<M: RawWireMode> BusPirate<M> {
+    /// Issue the mode's start condition.
+    ///
+    /// In 2-wire mode this is an I2C-style start; in 3-wire mode it asserts
+    /// chip select.
+    pub fn start(&mut self) -> Result<(), Error> {
+        debug!("Raw wire: Start");
+        let request = DataRequest::builder().start(true).stop(false).build();
+        self.send_data_request(request).map(drop)
+    }
+
+    /// Issue the mode's stop condition.
+    ///
+    /// In 2-wire mode this is an I2C-style stop; in 3-wire mode it releases
+    /// chip select.
+    pub fn stop(&mut self) -> Result<(), Error> {
+        debug!("Raw wire: Stop");
+        let request = DataRequest::builder().start(false).stop(true).build();
+        self.send_data_request(request).map(drop)
+    }
+
+    /// Issue the mode's alternate start condition.
+    pub fn start_alt(&mut self) -> Result<(), Error> {
+        debug!("Raw wire: Start (alt)");
+        let request = DataRequest::builder()
+            .start(false)
+            .start_alt(true)
+            .stop(false)
+            .build();
+        self.send_data_request(request).map(drop)
+    }
+
+    /// Issue the mode's alternate stop condition.
+    pub fn stop_alt(&mut self) -> Result<(), Error> {
+        debug!("Raw wire: Stop (alt)");
+        let request = DataRequest::builder()
+            .start(false)
+            .stop(false)
+            .stop_alt(true)
+            .build();
+        self.send_data_request(request).map(drop)
+    }
+
+    /// Clock out `bytes` without issuing any start or stop condition.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        debug!("Raw wire Write w:{}", bytes.len());
+        for range in chunk_ranges(bytes.len(), self.limits.max_write) {
+            self.write_bytes(&bytes[range])?;
+        }
+        Ok(())
+    }
+
+    /// Clock in `buf.len()` bytes without issuing any start or stop condition.
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        debug!("Raw wire Read r:{}", buf.len());
+        self.write_read(&[], buf)
+    }
+
+    /// Clock out `write`, then clock in `read.len()` bytes.
+    ///
+    /// The end of the write and the start of the read share a request, and
+    /// transfers too large for one request are split.
+    pub fn write_read(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        debug!("Raw wire Write-Read w:{} r:{}", write.len(), read.len());
+        let write_chunks: Vec<_> = chunk_ranges(write.len(), self.limits.max_write).collect();
+        let (last_write, write_chunks) = write_chunks
+            .split_last()
+            .expect("chunk_ranges yields at least one range");
+        for range in write_chunks {
+            self.write_bytes(&write[range.clone()])?;
+        }
+
+        let mut read_chunks = chunk_ranges(read.len(), self.limits.max_read);
+        let first_read = read_chunks
+            .next()
+            .expect("chunk_ranges yields at least one range");
+        self.raw_transfer(&write[last_write.clone()], &mut read[first_read])?;
+        for range in read_chunks {
+            self.raw_transfer(&[], &mut read[range])?;
+        }
+        Ok(())
+    }
+
+    fn raw_transfer(&mut self, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+        let request = DataRequest::builder()
+            .start(false)
+            .stop(false)
+            .bytes_to_write(write)
+            .bytes_to_read(read.len())
+            .build();
+        self.send_data_request(request)
+            .and_then(|received| copy_received(received, read))
+    }
+}
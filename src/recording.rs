@@ -0,0 +1,297 @@
+//! Record and replay of BPIO2 sessions.
+//!
+//! [`Recorder`] wraps a transport and logs each request and response frame as
+//! it passes through. [`Replay`] reads that log back and plays the part of the
+//! Bus Pirate, checking that each request matches the one recorded. Together
+//! they turn a session captured on the bench into a regression test.
+//!
+//! The log is plain text, with one frame per line:
+//!
+//! ```text
+//! <microseconds since start> > <COBS-encoded request, in hex>
+//! <microseconds since start> < <decoded response packet, in hex>
+//! ```
+//!
+//! Timestamps are for reference only; replay does not reproduce the timing.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use log::debug;
+
+use crate::{Response, Transport};
+
+const REQUEST: &str = ">";
+const RESPONSE: &str = "<";
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Split complete frames, including their sentinels, off the front of `buf`.
+fn take_frames(buf: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+    while let Some(end) = buf.iter().position(|&b| b == 0x00) {
+        frames.push(buf.drain(..=end).collect());
+    }
+    frames
+}
+
+/// A transport wrapper that logs every frame to a writer.
+pub struct Recorder<T, W> {
+    inner: T,
+    log: W,
+    started: Instant,
+    /// Request bytes written that do not yet form a complete frame.
+    request: Vec<u8>,
+    /// Response bytes read that do not yet form a complete frame.
+    response: Vec<u8>,
+}
+
+impl<T: Transport> Recorder<T, BufWriter<File>> {
+    /// Record the session on `inner` to a new file at `path`.
+    pub fn create(inner: T, path: impl AsRef<Path>) -> io::Result<Self> {
+        let log = BufWriter::new(File::create(path)?);
+        Ok(Self::new(inner, log))
+    }
+}
+
+impl<T: Transport, W: Write + Send> Recorder<T, W> {
+    pub fn new(inner: T, log: W) -> Self {
+        Self {
+            inner,
+            log,
+            started: Instant::now(),
+            request: Vec::new(),
+            response: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, direction: &str, frame: &[u8]) -> io::Result<()> {
+        let elapsed = self.started.elapsed().as_micros();
+        writeln!(self.log, "{elapsed} {direction} {}", to_hex(frame))?;
+        // Flush each frame so the log survives a crash.
+        self.log.flush()
+    }
+}
+
+impl<T: Transport, W: Write + Send> Write for Recorder<T, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.request.extend_from_slice(&buf[..n]);
+        for frame in take_frames(&mut self.request) {
+            self.record(REQUEST, &frame)?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport, W: Write + Send> Read for Recorder<T, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.response.extend_from_slice(&buf[..n]);
+        for frame in take_frames(&mut self.response) {
+            match Response::decode(&frame) {
+                Ok(Some(response)) => self.record(RESPONSE, &response.cobs_decoded)?,
+                // Leave malformed frames for the reader to report.
+                _ => debug!("Recorder: could not decode {}-byte frame", frame.len()),
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// One request in a recording and the responses that followed it.
+struct Exchange {
+    request: Vec<u8>,
+    responses: Vec<Vec<u8>>,
+}
+
+/// A transport that replays a recorded session.
+///
+/// Each request must match the next recorded request exactly, or the write
+/// fails with [`InvalidData`](io::ErrorKind::InvalidData). The responses
+/// recorded after it are then served to the reader. Requests beyond the end of
+/// the recording fail in the same way.
+///
+/// A replay is a handle to shared state, so keep a clone when passing it to
+/// [`BusPirate::from_transport`](crate::BusPirate::from_transport), and call
+/// [`Replay::finish`] on the clone afterwards to check that the code under
+/// test made every recorded request.
+#[derive(Clone)]
+pub struct Replay {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+struct ReplayState {
+    exchanges: VecDeque<Exchange>,
+    /// Number of requests replayed so far.
+    replayed: usize,
+    /// Request bytes written that do not yet form a complete frame.
+    request: Vec<u8>,
+    /// Encoded responses waiting to be read.
+    outgoing: VecDeque<u8>,
+}
+
+impl Replay {
+    /// Load a recording from a file written by [`Recorder`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a recording in the format written by [`Recorder`].
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut exchanges: VecDeque<Exchange> = VecDeque::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid_line = || invalid_data(format!("Invalid recording line {}", number + 1));
+
+            let mut fields = line.split_whitespace();
+            let (Some(_timestamp), Some(direction), Some(hex), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid_line());
+            };
+            let frame = from_hex(hex).ok_or_else(invalid_line)?;
+            match direction {
+                REQUEST => exchanges.push_back(Exchange {
+                    request: frame,
+                    responses: Vec::new(),
+                }),
+                RESPONSE => exchanges
+                    .back_mut()
+                    .ok_or_else(invalid_line)?
+                    .responses
+                    .push(frame),
+                _ => return Err(invalid_line()),
+            }
+        }
+        let state = ReplayState {
+            exchanges,
+            replayed: 0,
+            request: Vec::new(),
+            outgoing: VecDeque::new(),
+        };
+        Ok(Self {
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Check that every recorded request has been made.
+    ///
+    /// Fails with [`InvalidData`](io::ErrorKind::InvalidData) if any remain.
+    pub fn finish(&self) -> io::Result<()> {
+        let state = self.lock();
+        match state.exchanges.len() {
+            0 => Ok(()),
+            left => Err(invalid_data(format!(
+                "{left} recorded requests were not made, starting with request {}",
+                state.replayed + 1
+            ))),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl ReplayState {
+    fn replay(&mut self, request: &[u8]) -> io::Result<()> {
+        let Some(exchange) = self.exchanges.pop_front() else {
+            return Err(invalid_data(format!(
+                "Request {} is beyond the end of the recording",
+                self.replayed + 1
+            )));
+        };
+        if exchange.request != request {
+            return Err(invalid_data(format!(
+                "Request {} does not match the recording\n expected: {}\n   actual: {}",
+                self.replayed + 1,
+                to_hex(&exchange.request),
+                to_hex(request)
+            )));
+        }
+        self.replayed += 1;
+        for response in exchange.responses {
+            self.outgoing.extend(cobs::encode_vec(&response));
+            self.outgoing.push_back(0x00);
+        }
+        Ok(())
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        state.request.extend_from_slice(buf);
+        for frame in take_frames(&mut state.request) {
+            state.replay(&frame)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.outgoing.is_empty() {
+            // Nothing was recorded in response, so let the reader time out.
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        state.outgoing.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORDING: &str = "0 > 02aa00\n10 < aa\n20 > 02bb00\n30 < bb\n";
+
+    #[test]
+    fn finish_reports_requests_not_made() {
+        let mut replay = Replay::from_reader(RECORDING.as_bytes()).unwrap();
+        replay.write_all(&[0x02, 0xAA, 0x00]).unwrap();
+        let err = replay.finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn finish_succeeds_once_all_requests_are_made() {
+        let mut replay = Replay::from_reader(RECORDING.as_bytes()).unwrap();
+        replay
+            .write_all(&[0x02, 0xAA, 0x00, 0x02, 0xBB, 0x00])
+            .unwrap();
+        replay.finish().unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::{self, Read};
+
+use log::debug;
+
+use crate::{BusPirate, Error, bpio::DataRequest, modes::Spi};
+
+impl BusPirate<Spi> {
+    /// Write `command`, then stream back `len` bytes, all with chip select
+    /// asserted once.
+    ///
+    /// The command shares a request with the first chunk of data, and the rest
+    /// is read in chunks as large as the Bus Pirate allows, so this suits
+    /// dumping a whole flash chip after a read command and address.
+    ///
+    /// Chip select is released after the last byte is read, when a request
+    /// fails, or when the reader is dropped. Errors while reading are returned
+    /// as [`io::Error`]s.
+    pub fn read_stream(&mut self, command: &[u8], len: usize) -> Result<impl Read + '_, Error> {
+        debug!("SPI Read stream w:{} r:{len}", command.len());
+        let first = len.min(self.limits.max_read);
+        let mut chunk = vec![0; first];
+        let result = self.spi_write_read(command, &mut chunk, true, first == len);
+        // A failed last chunk has already released chip select, but no other.
+        self.release_on_error(result, first < len)?;
+        Ok(SpiReader {
+            bus_pirate: self,
+            remaining: len - first,
+            chunk,
+            position: 0,
+        })
+    }
+}
+
+/// Reads the data following a command, a chunk at a time.
+struct SpiReader<'a> {
+    bus_pirate: &'a mut BusPirate<Spi>,
+    /// Bytes not yet requested. Chip select is asserted while this is non-zero.
+    remaining: usize,
+    /// The chunk most recently received.
+    chunk: Vec<u8>,
+    /// How much of the chunk has been read.
+    position: usize,
+}
+
+impl SpiReader<'_> {
+    fn next_chunk(&mut self) -> Result<(), Error> {
+        let len = self.remaining.min(self.bus_pirate.limits.max_read);
+        let last = len == self.remaining;
+        self.chunk.resize(len, 0);
+        self.position = 0;
+        self.remaining -= len;
+        let result = self
+            .bus_pirate
+            .spi_write_read(&[], &mut self.chunk, false, last);
+        if result.is_err() {
+            // Nothing more can be read, so stop here.
+            self.chunk.clear();
+            self.remaining = 0;
+        }
+        self.bus_pirate.release_on_error(result, !last)
+    }
+
+    fn release(&mut self) {
+        if self.remaining > 0 {
+            self.remaining = 0;
+            let request = DataRequest::builder().start(false).stop(true).build();
+            // If that fails, ignore it as there is nothing more to be done.
+            let _ = self.bus_pirate.send_data_request(request);
+        }
+    }
+}
+
+impl Read for SpiReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = (&self.chunk[self.position..]).read(buf)?;
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl Drop for SpiReader<'_> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
@@ -0,0 +1,14 @@
+use std::io::{Read, Write};
+
+/// A byte stream connected to a Bus Pirate's BPIO2 interface.
+///
+/// This is implemented for any `Read + Write + Send` type, such as serial
+/// ports, TCP streams and in-memory pipes. Reads should not block indefinitely:
+/// the response deadline is only checked between reads, so a transport should
+/// return [`TimedOut`] or [`WouldBlock`] when no data arrives for a while.
+///
+/// [`TimedOut`]: std::io::ErrorKind::TimedOut
+/// [`WouldBlock`]: std::io::ErrorKind::WouldBlock
+pub trait Transport: Read + Write + Send {}
+
+impl<T: Read + Write + Send + ?Sized> Transport for T {}
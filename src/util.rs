@@ -1,3 +1,9 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::bpio::{DEFAULT_MAX_PACKET_SIZE, PACKET_OVERHEAD};
+use crate::{ModeConfiguration, Status};
+
 pub(crate) struct EncodedRequest {
     pub(crate) cobs_encoded: Vec<u8>,
 }
@@ -19,6 +25,74 @@ impl Response {
     pub(crate) fn new(data: Vec<u8>) -> Self {
         Self { cobs_decoded: data }
     }
+
+    /// Decode a COBS frame that ends with its sentinel.
+    ///
+    /// Returns `None` if the frame is incomplete.
+    pub(crate) fn decode(frame: &[u8]) -> Result<Option<Self>, cobs::DecodeError> {
+        // Decoding never lengthens the frame.
+        let mut packet = vec![0u8; frame.len()];
+        let mut decoder = cobs::CobsDecoder::new(&mut packet);
+        let Some(report) = decoder.push(frame)? else {
+            return Ok(None);
+        };
+        packet.truncate(report.frame_size());
+        Ok(Some(Self::new(packet)))
+    }
+}
+
+/// Largest amounts of data that can be written or read in a single request in
+/// the current mode.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TransferLimits {
+    pub(crate) max_write: usize,
+    pub(crate) max_read: usize,
+}
+
+impl TransferLimits {
+    /// Used for any limit the firmware does not report.
+    const FALLBACK: usize = 512;
+
+    pub(crate) fn from_status(status: &Status) -> Self {
+        fn reported(limit: u32) -> usize {
+            match limit {
+                0 => TransferLimits::FALLBACK,
+                n => n as usize,
+            }
+        }
+        let packet_data = max_packet_size(status).saturating_sub(PACKET_OVERHEAD);
+        Self {
+            max_write: reported(status.mode_max_write).min(packet_data).max(1),
+            max_read: reported(status.mode_max_read).min(packet_data).max(1),
+        }
+    }
+}
+
+/// The maximum packet size reported by the firmware, or a default if it reports none.
+pub(crate) fn max_packet_size(status: &Status) -> usize {
+    match status.mode_max_packet_size {
+        0 => DEFAULT_MAX_PACKET_SIZE,
+        n => n as usize,
+    }
+}
+
+impl Default for TransferLimits {
+    fn default() -> Self {
+        Self {
+            max_write: Self::FALLBACK,
+            max_read: Self::FALLBACK,
+        }
+    }
+}
+
+/// Split `0..len` into consecutive ranges of at most `size`.
+///
+/// A single empty range is produced if `len` is zero, so that a request is still
+/// sent for empty transfers (for example, to issue start and stop conditions).
+pub(crate) fn chunk_ranges(len: usize, size: usize) -> impl Iterator<Item = Range<usize>> {
+    let size = size.max(1);
+    let count = len.div_ceil(size).max(1);
+    (0..count).map(move |i| (i * size).min(len)..((i + 1) * size).min(len))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -79,3 +153,145 @@ impl ClockPhase {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+impl DataBits {
+    pub(crate) fn for_bpio(self) -> u8 {
+        match self {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Parity {
+    None,
+    Even,
+}
+
+impl Parity {
+    /// Convert into a bool for BPIO requests.
+    ///
+    /// BPIO only distinguishes between no parity (false) and even parity (true).
+    pub(crate) fn for_bpio(self) -> bool {
+        match self {
+            Parity::None => false,
+            Parity::Even => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+impl StopBits {
+    pub(crate) fn for_bpio(self) -> u8 {
+        match self {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FlowControl {
+    None,
+    RtsCts,
+}
+
+impl FlowControl {
+    pub(crate) fn for_bpio(self) -> bool {
+        matches!(self, FlowControl::RtsCts)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SignalPolarity {
+    Normal,
+    Inverted,
+}
+
+impl SignalPolarity {
+    pub(crate) fn for_bpio(self) -> bool {
+        matches!(self, SignalPolarity::Inverted)
+    }
+}
+
+/// Serial line settings for UART mode.
+///
+/// Only the baud rate is required; the rest default to 8N1 with no flow
+/// control and normal signal polarity.
+#[derive(Debug, Clone, bon::Builder)]
+pub struct UartConfig {
+    baud_rate: u32,
+    #[builder(default = DataBits::Eight)]
+    data_bits: DataBits,
+    #[builder(default = Parity::None)]
+    parity: Parity,
+    #[builder(default = StopBits::One)]
+    stop_bits: StopBits,
+    #[builder(default = FlowControl::None)]
+    flow_control: FlowControl,
+    #[builder(default = SignalPolarity::Normal)]
+    signal_polarity: SignalPolarity,
+}
+
+impl UartConfig {
+    pub(crate) fn mode_configuration(&self) -> ModeConfiguration {
+        ModeConfiguration::builder()
+            .speed(self.baud_rate)
+            .data_bits(self.data_bits.for_bpio())
+            .parity(self.parity.for_bpio())
+            .stop_bits(self.stop_bits.for_bpio())
+            .flow_control(self.flow_control.for_bpio())
+            .signal_inversion(self.signal_polarity.for_bpio())
+            .build()
+    }
+}
+
+/// Serial line and timing settings for half-duplex UART mode.
+///
+/// Only the baud rate is required; the line settings default to 8N1 with
+/// normal signal polarity. By default reception starts immediately after
+/// transmission and waits up to 100ms for a complete response.
+#[derive(Debug, Clone, bon::Builder)]
+pub struct HdUartConfig {
+    baud_rate: u32,
+    #[builder(default = DataBits::Eight)]
+    data_bits: DataBits,
+    #[builder(default = Parity::None)]
+    parity: Parity,
+    #[builder(default = StopBits::One)]
+    stop_bits: StopBits,
+    #[builder(default = SignalPolarity::Normal)]
+    signal_polarity: SignalPolarity,
+    #[builder(default = Duration::ZERO)]
+    pub(crate) turnaround: Duration,
+    #[builder(default = Duration::from_millis(100))]
+    pub(crate) timeout: Duration,
+}
+
+impl HdUartConfig {
+    pub(crate) fn mode_configuration(&self) -> ModeConfiguration {
+        ModeConfiguration::builder()
+            .speed(self.baud_rate)
+            .data_bits(self.data_bits.for_bpio())
+            .parity(self.parity.for_bpio())
+            .stop_bits(self.stop_bits.for_bpio())
+            .signal_inversion(self.signal_polarity.for_bpio())
+            .build()
+    }
+}
@@ -0,0 +1,35 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use buspirate_hal::emulator::{Emulator, I2cPeripheral, SpiPeripheral};
+use buspirate_hal::{BusPirate, ChipSelectPolarity, ClockPhase, ClockPolarity, modes};
+
+/// Test data that does not repeat every 256 bytes.
+pub fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+}
+
+/// An emulator in I2C mode with `device` on the bus.
+pub fn i2c_bus(device: impl I2cPeripheral + 'static) -> BusPirate<modes::I2c> {
+    BusPirate::from_transport(Emulator::new().with_i2c_device(device))
+        .unwrap()
+        .enter_i2c_mode(400_000, false, None)
+        .unwrap()
+}
+
+/// An emulator in SPI mode 0 with `device` on the bus.
+pub fn spi_bus(device: impl SpiPeripheral + 'static) -> BusPirate<modes::Spi> {
+    BusPirate::from_transport(Emulator::new().with_spi_device(device))
+        .unwrap()
+        .enter_spi_mode(
+            1_000_000,
+            8,
+            ClockPolarity::ActiveLow,
+            ClockPhase::LeadingEdge,
+            ChipSelectPolarity::ActiveLow,
+            None,
+        )
+        .unwrap()
+}
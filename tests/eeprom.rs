@@ -0,0 +1,103 @@
+//! 24xx EEPROM reads and writes, against the emulator's EEPROM model.
+
+use std::sync::{Arc, Mutex};
+
+use buspirate_hal::eeprom::{Eeprom, Part};
+use buspirate_hal::emulator::Eeprom24;
+use buspirate_hal::{BusPirate, Error, modes};
+
+mod common;
+
+use common::{i2c_bus, pattern};
+
+fn eeprom(model: Eeprom24, part: Part) -> (Arc<Mutex<Eeprom24>>, Eeprom<BusPirate<modes::I2c>>) {
+    let model = Arc::new(Mutex::new(model));
+    let bp = i2c_bus(Arc::clone(&model));
+    (model, Eeprom::new(bp, part))
+}
+
+/// Write `len` bytes at `offset`, then check the model and read it all back.
+fn write_and_read_back(model: Eeprom24, part: Part, offset: usize, len: usize) {
+    let (model, mut eeprom) = eeprom(model, part);
+    let data = pattern(len);
+    eeprom.write(offset, &data).unwrap();
+
+    let mut expected = vec![0xFF; part.size()];
+    expected[offset..offset + len].copy_from_slice(&data);
+    assert_eq!(model.lock().unwrap().contents(), expected);
+    assert_eq!(eeprom.read_all().unwrap(), expected);
+}
+
+#[test]
+fn one_byte_address_across_blocks() {
+    // 24xx16: eight 256-byte blocks, each at its own I2C address.
+    write_and_read_back(Eeprom24::new(2048, 16), Part::C16, 0x1F0, 0x30);
+}
+
+#[test]
+fn two_byte_address_across_pages() {
+    write_and_read_back(Eeprom24::new(64 * 1024, 128), Part::C512, 0x7F0, 300);
+}
+
+#[test]
+fn two_byte_address_across_blocks() {
+    write_and_read_back(Eeprom24::new(128 * 1024, 256), Part::CM01, 0xFFF0, 0x40);
+}
+
+#[test]
+fn block_select_in_place_of_a2() {
+    // 24xx1025: the second 64KiB block is at 0x54, and 0x51 is another part.
+    write_and_read_back(
+        Eeprom24::new(128 * 1024, 128).with_block_select_shift(2),
+        Part::C1025,
+        0xFFF0,
+        0x40,
+    );
+}
+
+#[test]
+fn write_polls_through_the_write_cycle() {
+    write_and_read_back(
+        Eeprom24::new(256, 8).with_write_cycle_polls(3),
+        Part::C02,
+        0x04,
+        0x10,
+    );
+}
+
+#[test]
+fn part_stuck_busy_times_out() {
+    let (_, mut eeprom) = eeprom(
+        Eeprom24::new(256, 8).with_write_cycle_polls(u32::MAX),
+        Part::C02,
+    );
+    assert!(matches!(
+        eeprom.write(0x00, &[0x12]),
+        Err(Error::BusyTimeout { .. })
+    ));
+}
+
+#[test]
+fn verify_reports_the_first_difference() {
+    let contents = pattern(256);
+    let mut model = Eeprom24::new(256, 8);
+    model.contents_mut().copy_from_slice(&contents);
+    let (_, mut eeprom) = eeprom(model, Part::C02);
+
+    eeprom.verify(0x10, &contents[0x10..0x20]).unwrap();
+    let mut data = contents[0x10..0x20].to_vec();
+    data[5] ^= 0xFF;
+    assert!(matches!(
+        eeprom.verify(0x10, &data),
+        Err(Error::VerifyFailed { address: 0x15 })
+    ));
+}
+
+#[test]
+fn out_of_range_is_rejected() {
+    let (_, mut eeprom) = eeprom(Eeprom24::new(256, 8), Part::C02);
+    assert!(matches!(
+        eeprom.write(0xF8, &[0; 9]),
+        Err(Error::InvalidAddress { address: 0xF8 })
+    ));
+}
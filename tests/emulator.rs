@@ -0,0 +1,169 @@
+//! The embedded-hal I2C and SPI implementations, against the emulator's
+//! EEPROM and NOR flash models.
+
+use std::sync::{Arc, Mutex};
+
+use buspirate_hal::emulator::{Eeprom24, SpiNorFlash};
+use embedded_hal::i2c::{self, ErrorKind, I2c, NoAcknowledgeSource};
+use embedded_hal::spi::{Operation, SpiBus, SpiDevice};
+
+mod common;
+
+use common::{i2c_bus, pattern, spi_bus};
+
+const EEPROM_ADDRESS: u8 = 0x50;
+const JEDEC_ID: [u8; 3] = [0xEF, 0x40, 0x16];
+
+#[test]
+fn i2c_write_then_read_back() {
+    let eeprom = Arc::new(Mutex::new(Eeprom24::new(256, 8)));
+    let mut bp = i2c_bus(Arc::clone(&eeprom));
+
+    bp.write(EEPROM_ADDRESS, &[0x10, 1, 2, 3, 4]).unwrap();
+    assert_eq!(eeprom.lock().unwrap().contents()[0x10..0x14], [1, 2, 3, 4]);
+
+    let mut buf = [0u8; 4];
+    bp.write_read(EEPROM_ADDRESS, &[0x10], &mut buf).unwrap();
+    assert_eq!(buf, [1, 2, 3, 4]);
+
+    // A plain read carries on from where the last one stopped.
+    let mut buf = [0u8; 2];
+    bp.read(EEPROM_ADDRESS, &mut buf).unwrap();
+    assert_eq!(buf, [0xFF, 0xFF]);
+}
+
+#[test]
+fn i2c_transaction_merges_adjacent_operations() {
+    let contents = pattern(4096);
+    let mut eeprom = Eeprom24::new(4096, 32);
+    eeprom.contents_mut().copy_from_slice(&contents);
+    let eeprom = Arc::new(Mutex::new(eeprom));
+    let mut bp = i2c_bus(Arc::clone(&eeprom));
+
+    // The two address writes form one write, and the reads one read.
+    let (mut first, mut second) = ([0u8; 2], [0u8; 3]);
+    bp.transaction(
+        EEPROM_ADDRESS,
+        &mut [
+            i2c::Operation::Write(&[0x01]),
+            i2c::Operation::Write(&[0x23]),
+            i2c::Operation::Read(&mut first),
+            i2c::Operation::Read(&mut second),
+        ],
+    )
+    .unwrap();
+    assert_eq!(first, contents[0x123..0x125]);
+    assert_eq!(second, contents[0x125..0x128]);
+}
+
+#[test]
+fn i2c_read_longer_than_one_request() {
+    let contents = pattern(4096);
+    let mut eeprom = Eeprom24::new(4096, 32);
+    eeprom.contents_mut().copy_from_slice(&contents);
+    let eeprom = Arc::new(Mutex::new(eeprom));
+    let mut bp = i2c_bus(Arc::clone(&eeprom));
+
+    let mut buf = vec![0u8; 1500];
+    bp.write_read(EEPROM_ADDRESS, &[0x00, 0x40], &mut buf)
+        .unwrap();
+    assert_eq!(buf, contents[0x40..0x40 + 1500]);
+}
+
+#[test]
+fn i2c_write_longer_than_one_request_then_read() {
+    let contents = pattern(65536);
+    let mut eeprom = Eeprom24::new(65536, 1024);
+    eeprom.contents_mut().copy_from_slice(&contents);
+    let eeprom = Arc::new(Mutex::new(eeprom));
+    let mut bp = i2c_bus(Arc::clone(&eeprom));
+
+    // The repeated start for the read abandons the data, so the read starts
+    // from the address written.
+    let mut write = vec![0x01, 0x00];
+    write.extend(pattern(600));
+    let mut buf = [0u8; 16];
+    bp.transaction(
+        EEPROM_ADDRESS,
+        &mut [
+            i2c::Operation::Write(&write),
+            i2c::Operation::Read(&mut buf),
+        ],
+    )
+    .unwrap();
+    assert_eq!(buf, contents[0x100..0x110]);
+    assert_eq!(eeprom.lock().unwrap().contents(), contents);
+}
+
+#[test]
+fn i2c_missing_device_is_an_address_nack() {
+    let eeprom = Arc::new(Mutex::new(Eeprom24::new(256, 8)));
+    let mut bp = i2c_bus(Arc::clone(&eeprom));
+
+    let err = bp.write(EEPROM_ADDRESS + 1, &[0x00]).unwrap_err();
+    assert_eq!(
+        i2c::Error::kind(&err),
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+    );
+    // The bus is released, so the next transfer works.
+    let mut buf = [0u8; 1];
+    bp.write_read(EEPROM_ADDRESS, &[0x00], &mut buf).unwrap();
+}
+
+#[test]
+fn spi_device_reads_jedec_id() {
+    let flash = Arc::new(Mutex::new(SpiNorFlash::new(JEDEC_ID, 1 << 20)));
+    let mut bp = spi_bus(Arc::clone(&flash));
+
+    let mut id = [0u8; 3];
+    bp.transaction(&mut [Operation::Write(&[0x9F]), Operation::Read(&mut id)])
+        .unwrap();
+    assert_eq!(id, JEDEC_ID);
+}
+
+#[test]
+fn spi_device_read_longer_than_one_request() {
+    let contents = pattern(1 << 20);
+    let mut flash = SpiNorFlash::new(JEDEC_ID, 1 << 20);
+    flash.contents_mut().copy_from_slice(&contents);
+    let flash = Arc::new(Mutex::new(flash));
+    let mut bp = spi_bus(Arc::clone(&flash));
+
+    let mut buf = vec![0u8; 1500];
+    bp.transaction(&mut [
+        Operation::Write(&[0x03, 0x01, 0x00, 0x00]),
+        Operation::Read(&mut buf),
+    ])
+    .unwrap();
+    assert_eq!(buf, contents[0x10000..0x10000 + 1500]);
+}
+
+#[test]
+fn spi_device_writes_are_separate_commands() {
+    let flash = Arc::new(Mutex::new(
+        SpiNorFlash::new(JEDEC_ID, 1 << 20).with_busy_polls(0),
+    ));
+    let mut bp = spi_bus(Arc::clone(&flash));
+
+    // Write enable, then page program: each needs its own chip select cycle.
+    SpiDevice::write(&mut bp, &[0x06]).unwrap();
+    SpiDevice::write(&mut bp, &[0x02, 0x00, 0x01, 0x00, 0xAA, 0x55]).unwrap();
+    assert_eq!(
+        flash.lock().unwrap().contents()[0x100..0x103],
+        [0xAA, 0x55, 0xFF]
+    );
+}
+
+#[test]
+fn spi_bus_transfer_is_full_duplex() {
+    let flash = Arc::new(Mutex::new(SpiNorFlash::new(JEDEC_ID, 1 << 20)));
+    let mut bp = spi_bus(Arc::clone(&flash));
+
+    let mut words = [0x9F, 0x00, 0x00, 0x00];
+    SpiBus::transfer_in_place(&mut bp, &mut words).unwrap();
+    assert_eq!(words, [0xFF, JEDEC_ID[0], JEDEC_ID[1], JEDEC_ID[2]]);
+
+    let mut read = [0u8; 2];
+    SpiBus::transfer(&mut bp, &mut read, &[0x9F, 0x00]).unwrap();
+    assert_eq!(read, [0xFF, JEDEC_ID[0]]);
+}
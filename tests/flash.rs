@@ -0,0 +1,106 @@
+//! SPI NOR flash detection, erasing and programming, against the emulator's
+//! NOR flash model.
+
+use std::sync::{Arc, Mutex};
+
+use buspirate_hal::Error;
+use buspirate_hal::emulator::SpiNorFlash;
+use buspirate_hal::flash::{EraseType, FlashInfo, SpiFlash};
+
+mod common;
+
+use common::{pattern, spi_bus};
+
+const JEDEC_ID: [u8; 3] = [0xEF, 0x40, 0x14];
+const SIZE: usize = 1 << 20;
+
+fn prefilled(contents: &[u8]) -> Arc<Mutex<SpiNorFlash>> {
+    let mut flash = SpiNorFlash::new(JEDEC_ID, contents.len());
+    flash.contents_mut().copy_from_slice(contents);
+    Arc::new(Mutex::new(flash))
+}
+
+#[test]
+fn detect_reads_sfdp_geometry() {
+    let flash = Arc::new(Mutex::new(SpiNorFlash::new(JEDEC_ID, SIZE)));
+    let mut bp = spi_bus(Arc::clone(&flash));
+
+    let info = FlashInfo::detect(&mut bp).unwrap();
+    assert_eq!(
+        info,
+        FlashInfo {
+            jedec_id: JEDEC_ID,
+            size: SIZE,
+            page_size: 256,
+            erase_types: vec![
+                EraseType {
+                    size: 4 * 1024,
+                    opcode: 0x20,
+                },
+                EraseType {
+                    size: 64 * 1024,
+                    opcode: 0xD8,
+                },
+            ],
+            chip_erase_opcode: 0x60,
+        }
+    );
+}
+
+#[test]
+fn write_replaces_only_the_sectors_it_covers() {
+    let contents = pattern(SIZE);
+    let flash = prefilled(&contents);
+    let mut spi_flash = SpiFlash::new(spi_bus(Arc::clone(&flash))).unwrap();
+
+    // Crosses the boundary between the first two 4KiB sectors, and several
+    // pages.
+    let data = vec![0x5A; 0x300];
+    spi_flash.write(0x0E80, &data).unwrap();
+
+    let flash = flash.lock().unwrap();
+    let memory = flash.contents();
+    assert!(memory[..0x0E80].iter().all(|&b| b == 0xFF));
+    assert_eq!(memory[0x0E80..0x1180], data);
+    assert!(memory[0x1180..0x2000].iter().all(|&b| b == 0xFF));
+    assert_eq!(memory[0x2000..], contents[0x2000..]);
+}
+
+#[test]
+fn erase_chip_blanks_the_part() {
+    let flash = prefilled(&pattern(SIZE));
+    let mut spi_flash = SpiFlash::new(spi_bus(Arc::clone(&flash))).unwrap();
+
+    spi_flash.erase_chip().unwrap();
+    assert!(flash.lock().unwrap().contents().iter().all(|&b| b == 0xFF));
+}
+
+#[test]
+fn write_protection_is_checked() {
+    let flash = Arc::new(Mutex::new(SpiNorFlash::new(JEDEC_ID, SIZE)));
+    let mut spi_flash = SpiFlash::new(spi_bus(Arc::clone(&flash))).unwrap();
+
+    spi_flash.set_write_protection(true).unwrap();
+    assert!(matches!(
+        spi_flash.write(0, &[0x00]),
+        Err(Error::WriteProtected)
+    ));
+    assert_eq!(flash.lock().unwrap().contents()[0], 0xFF);
+
+    spi_flash.set_write_protection(false).unwrap();
+    spi_flash.write(0, &[0x00]).unwrap();
+    assert_eq!(flash.lock().unwrap().contents()[0], 0x00);
+}
+
+#[test]
+fn part_stuck_busy_times_out() {
+    let flash = Arc::new(Mutex::new(
+        SpiNorFlash::new(JEDEC_ID, SIZE).with_busy_polls(u32::MAX),
+    ));
+    let mut spi_flash = SpiFlash::new(spi_bus(Arc::clone(&flash))).unwrap();
+
+    assert!(matches!(
+        spi_flash.program(0, &[0x00]),
+        Err(Error::BusyTimeout { .. })
+    ));
+}
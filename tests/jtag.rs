@@ -0,0 +1,59 @@
+//! Bit-banged JTAG against the emulator's TAP model on the IO pins.
+
+use std::sync::{Arc, Mutex};
+
+use buspirate_hal::emulator::{Emulator, JtagTap};
+use buspirate_hal::jtag::{IdCode, JtagPins, TapState};
+use buspirate_hal::{BusPirate, modes};
+
+const IDCODE: u32 = 0x4BA0_0477;
+
+fn jtag(tap: &Arc<Mutex<JtagTap>>) -> BusPirate<modes::Jtag> {
+    BusPirate::from_transport(Emulator::new().with_io_device(Arc::clone(tap)))
+        .unwrap()
+        .enter_jtag_mode(JtagPins::default(), None)
+        .unwrap()
+}
+
+#[test]
+fn scan_chain_reads_the_idcode() {
+    let tap = Arc::new(Mutex::new(JtagTap::new(IDCODE)));
+    let mut bp = jtag(&tap);
+
+    assert_eq!(bp.scan_chain().unwrap(), [Some(IdCode(IDCODE))]);
+    assert_eq!(tap.lock().unwrap().state(), TapState::RunTestIdle);
+}
+
+#[test]
+fn shift_ir_captures_the_fixed_bits() {
+    let tap = Arc::new(Mutex::new(JtagTap::new(IDCODE)));
+    let mut bp = jtag(&tap);
+
+    assert_eq!(
+        bp.shift_ir(&[true; 4]).unwrap(),
+        [true, false, false, false]
+    );
+    // BYPASS is now selected, so the data register is a single zero bit.
+    assert_eq!(bp.shift_dr(&[true, true]).unwrap(), [false, true]);
+    assert_eq!(tap.lock().unwrap().shifted_bits(), 6);
+}
+
+#[test]
+fn zero_length_shift_shifts_nothing() {
+    let tap = Arc::new(Mutex::new(JtagTap::new(IDCODE)));
+    let mut bp = jtag(&tap);
+
+    assert!(bp.shift_ir(&[]).unwrap().is_empty());
+    assert!(bp.shift_dr(&[]).unwrap().is_empty());
+    assert_eq!(tap.lock().unwrap().shifted_bits(), 0);
+    assert_eq!(tap.lock().unwrap().state(), TapState::RunTestIdle);
+    assert_eq!(bp.tap_state(), TapState::RunTestIdle);
+
+    // The IDCODE is still selected after the empty instruction shift.
+    let idcode = bp.shift_dr(&[false; 32]).unwrap();
+    let value = idcode
+        .iter()
+        .enumerate()
+        .fold(0u32, |value, (i, &bit)| value | (u32::from(bit) << i));
+    assert_eq!(value, IDCODE);
+}
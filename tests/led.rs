@@ -0,0 +1,29 @@
+//! LED frames against the emulator, which accepts and discards the data.
+
+use buspirate_hal::emulator::Emulator;
+use buspirate_hal::{BusPirate, Error, LedType, Rgb};
+
+fn strip(led_type: LedType) -> BusPirate<buspirate_hal::modes::Led> {
+    BusPirate::from_transport(Emulator::new())
+        .unwrap()
+        .enter_led_mode(led_type, None)
+        .unwrap()
+}
+
+#[test]
+fn apa102_frame_longer_than_one_request() {
+    let mut bp = strip(LedType::Apa102);
+
+    bp.write_colors(&[Rgb::new(1, 2, 3); 300]).unwrap();
+}
+
+#[test]
+fn ws2812_frame_must_fit_one_request() {
+    let mut bp = strip(LedType::Ws2812);
+
+    bp.write_colors(&[Rgb::new(1, 2, 3); 100]).unwrap();
+    assert!(matches!(
+        bp.write_colors(&[Rgb::new(1, 2, 3); 300]),
+        Err(Error::Unsupported(_))
+    ));
+}
@@ -0,0 +1,68 @@
+//! 1-Wire ROM commands and search through a DS2482 bridge, against the
+//! emulator's DS2482 model.
+
+use buspirate_hal::ds2482::Ds2482;
+use buspirate_hal::emulator;
+use buspirate_hal::onewire::{OneWireBus, OneWireSearch, RomCode};
+use buspirate_hal::{BusPirate, modes};
+
+mod common;
+
+use common::i2c_bus;
+
+/// ROM codes in the order a search finds them: at each bit where devices
+/// differ, from the least significant bit of the family code, zero first.
+const ROMS: [RomCode; 5] = [
+    RomCode([0x10, 0x34, 0x12, 0x00, 0x00, 0x00, 0x00, 0x35]),
+    RomCode([0x28, 0x3C, 0x2C, 0x1B, 0x0A, 0x00, 0x00, 0x91]),
+    RomCode([0x28, 0x3D, 0x2C, 0x1B, 0x0A, 0x00, 0x00, 0xA6]),
+    RomCode([0x22, 0x01, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x49]),
+    RomCode([0x3B, 0x3D, 0x2C, 0x1B, 0x0A, 0x00, 0x00, 0x1A]),
+];
+
+fn bridge(model: emulator::Ds2482) -> Ds2482<BusPirate<modes::I2c>> {
+    let mut bridge = Ds2482::new(i2c_bus(model));
+    bridge.device_reset().unwrap();
+    bridge
+}
+
+#[test]
+fn search_finds_every_device_in_rom_order() {
+    let model = [3, 0, 4, 2, 1]
+        .into_iter()
+        .fold(emulator::Ds2482::new(), |model, i| {
+            model.with_device(ROMS[i])
+        });
+    let mut bridge = bridge(model);
+
+    assert!(ROMS.iter().all(RomCode::is_valid));
+    assert_eq!(bridge.search().unwrap(), ROMS);
+}
+
+#[test]
+fn alarm_search_finds_only_alarmed_devices() {
+    let model = emulator::Ds2482::new()
+        .with_device(ROMS[0])
+        .with_alarm_device(ROMS[4])
+        .with_device(ROMS[2])
+        .with_alarm_device(ROMS[1]);
+    let mut bridge = bridge(model);
+
+    assert_eq!(bridge.alarm_search().unwrap(), [ROMS[1], ROMS[4]]);
+}
+
+#[test]
+fn read_rom_of_a_single_device() {
+    let mut bridge = bridge(emulator::Ds2482::new().with_device(ROMS[3]));
+
+    assert_eq!(bridge.read_rom().unwrap(), Some(ROMS[3]));
+    assert_eq!(bridge.search().unwrap(), [ROMS[3]]);
+}
+
+#[test]
+fn empty_bus_has_no_presence() {
+    let mut bridge = bridge(emulator::Ds2482::new());
+
+    assert!(!bridge.reset().unwrap());
+    assert!(bridge.search().unwrap().is_empty());
+}
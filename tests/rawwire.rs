@@ -0,0 +1,33 @@
+//! Raw 2-wire and 3-wire transfers, against the emulator's idle buses.
+
+use buspirate_hal::emulator::Emulator;
+use buspirate_hal::{BusPirate, ChipSelectPolarity};
+
+#[test]
+fn two_wire_transfers_longer_than_one_request() {
+    let mut bp = BusPirate::from_transport(Emulator::new())
+        .unwrap()
+        .enter_two_wire_mode(100_000, None)
+        .unwrap();
+
+    bp.start().unwrap();
+    bp.write(&[0xA5; 2000]).unwrap();
+    let mut buf = [0u8; 2000];
+    bp.read(&mut buf).unwrap();
+    bp.stop().unwrap();
+    assert!(buf.iter().all(|&b| b == 0xFF));
+}
+
+#[test]
+fn three_wire_write_read_longer_than_one_request() {
+    let mut bp = BusPirate::from_transport(Emulator::new())
+        .unwrap()
+        .enter_three_wire_mode(1_000_000, ChipSelectPolarity::ActiveLow, None)
+        .unwrap();
+
+    let mut buf = [0u8; 1500];
+    bp.write_read(&[0x0F; 1500], &mut buf).unwrap();
+    assert!(buf.iter().all(|&b| b == 0xFF));
+
+    bp.write_read(&[], &mut []).unwrap();
+}
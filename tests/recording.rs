@@ -0,0 +1,78 @@
+//! Recording a session against the emulator and replaying it.
+
+use std::path::{Path, PathBuf};
+
+use buspirate_hal::emulator::{Emulator, SpiNorFlash};
+use buspirate_hal::flash::SpiFlash;
+use buspirate_hal::recording::{Recorder, Replay};
+use buspirate_hal::{BusPirate, ChipSelectPolarity, ClockPhase, ClockPolarity, Error, Transport};
+
+mod common;
+
+use common::pattern;
+
+const JEDEC_ID: [u8; 3] = [0xEF, 0x40, 0x14];
+
+/// A log file unique to this test process.
+fn log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("buspirate-hal-{}-{name}.log", std::process::id()))
+}
+
+/// Detect the flash and read `len` bytes from `address`.
+fn read_flash(
+    transport: impl Transport + 'static,
+    address: usize,
+    len: usize,
+) -> Result<Vec<u8>, Error> {
+    let bp = BusPirate::from_transport(transport)?.enter_spi_mode(
+        1_000_000,
+        8,
+        ClockPolarity::ActiveLow,
+        ClockPhase::LeadingEdge,
+        ChipSelectPolarity::ActiveLow,
+        None,
+    )?;
+    let mut flash = SpiFlash::new(bp)?;
+    let mut buf = vec![0u8; len];
+    flash.read(address, &mut buf)?;
+    Ok(buf)
+}
+
+fn record(path: &Path) -> Vec<u8> {
+    let mut model = SpiNorFlash::new(JEDEC_ID, 1 << 20);
+    model.contents_mut()[..4096].copy_from_slice(&pattern(4096));
+    let emulator = Emulator::new().with_spi_device(model);
+    read_flash(Recorder::create(emulator, path).unwrap(), 0x100, 1000).unwrap()
+}
+
+#[test]
+fn replay_matches_the_recording() {
+    let path = log_path("replay");
+    let recorded = record(&path);
+    assert_eq!(recorded, pattern(4096)[0x100..0x100 + 1000]);
+
+    let replay = Replay::open(&path).unwrap();
+    let replayed = read_flash(replay.clone(), 0x100, 1000).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(replayed, recorded);
+    replay.finish().unwrap();
+}
+
+#[test]
+fn replay_rejects_a_different_session() {
+    let path = log_path("diverge");
+    record(&path);
+
+    // Reading elsewhere diverges from the recording at the read request.
+    let replay = Replay::open(&path).unwrap();
+    let result = read_flash(replay.clone(), 0x200, 1000);
+    assert!(result.is_err());
+    assert!(replay.finish().is_err());
+
+    // Stopping early leaves recorded requests unmade.
+    let replay = Replay::open(&path).unwrap();
+    BusPirate::from_transport(replay.clone()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(replay.finish().is_err());
+}
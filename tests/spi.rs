@@ -0,0 +1,147 @@
+//! Streaming SPI reads against the emulator, with a request failing part way.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use buspirate_hal::emulator::{Emulator, SpiPeripheral};
+use buspirate_hal::{BusPirate, ChipSelectPolarity, ClockPhase, ClockPolarity, modes};
+
+/// A device that sends back a counter and remembers whether it is selected.
+#[derive(Default)]
+struct Probe {
+    selected: bool,
+    count: u8,
+}
+
+impl SpiPeripheral for Probe {
+    fn select(&mut self) {
+        self.selected = true;
+    }
+
+    fn transfer(&mut self, _mosi: u8) -> u8 {
+        self.count = self.count.wrapping_add(1);
+        self.count
+    }
+
+    fn deselect(&mut self) {
+        self.selected = false;
+    }
+}
+
+/// Passes frames through to the emulator, except that the one numbered
+/// `fail_at` (counting from zero) is dropped and its write fails.
+struct FailingTransport {
+    inner: Emulator,
+    sent: Arc<AtomicUsize>,
+    fail_at: Arc<AtomicUsize>,
+}
+
+impl Write for FailingTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let sent = self.sent.load(Ordering::SeqCst);
+        if sent == self.fail_at.load(Ordering::SeqCst) {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            return Err(io::Error::other("injected failure"));
+        }
+        let n = self.inner.write(buf)?;
+        let frames = buf[..n].iter().filter(|&&b| b == 0x00).count();
+        self.sent.fetch_add(frames, Ordering::SeqCst);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Read for FailingTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+struct Setup {
+    bp: BusPirate<modes::Spi>,
+    probe: Arc<Mutex<Probe>>,
+    sent: Arc<AtomicUsize>,
+    fail_at: Arc<AtomicUsize>,
+}
+
+fn setup() -> Setup {
+    let probe = Arc::new(Mutex::new(Probe::default()));
+    let sent = Arc::new(AtomicUsize::new(0));
+    let fail_at = Arc::new(AtomicUsize::new(usize::MAX));
+    let transport = FailingTransport {
+        inner: Emulator::new().with_spi_device(Arc::clone(&probe)),
+        sent: Arc::clone(&sent),
+        fail_at: Arc::clone(&fail_at),
+    };
+    let bp = BusPirate::from_transport(transport)
+        .unwrap()
+        .enter_spi_mode(
+            1_000_000,
+            8,
+            ClockPolarity::ActiveLow,
+            ClockPhase::LeadingEdge,
+            ChipSelectPolarity::ActiveLow,
+            None,
+        )
+        .unwrap();
+    Setup {
+        bp,
+        probe,
+        sent,
+        fail_at,
+    }
+}
+
+#[test]
+fn stream_reads_every_chunk() {
+    let Setup { mut bp, probe, .. } = setup();
+
+    let mut data = Vec::new();
+    bp.read_stream(&[0x03, 0x00, 0x00, 0x00], 2000)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    let expected: Vec<u8> = (5..2005).map(|i: usize| i as u8).collect();
+    assert_eq!(data, expected);
+    assert!(!probe.lock().unwrap().selected);
+}
+
+#[test]
+fn failure_within_the_command_releases_chip_select() {
+    let Setup {
+        mut bp,
+        probe,
+        sent,
+        fail_at,
+    } = setup();
+
+    // A command longer than one request asserts chip select with its first
+    // part, and the request with the rest of it fails.
+    fail_at.store(sent.load(Ordering::SeqCst) + 1, Ordering::SeqCst);
+    assert!(bp.read_stream(&[0x03; 600], 2000).is_err());
+    assert!(!probe.lock().unwrap().selected);
+}
+
+#[test]
+fn failure_part_way_through_releases_chip_select() {
+    let Setup {
+        mut bp,
+        probe,
+        sent,
+        fail_at,
+    } = setup();
+
+    // The first chunk goes through, and the second request fails.
+    fail_at.store(sent.load(Ordering::SeqCst) + 1, Ordering::SeqCst);
+    let mut reader = bp.read_stream(&[0x03, 0x00, 0x00, 0x00], 2000).unwrap();
+    assert!(probe.lock().unwrap().selected);
+
+    let mut data = Vec::new();
+    assert!(reader.read_to_end(&mut data).is_err());
+    assert!(!probe.lock().unwrap().selected);
+    assert!(!data.is_empty() && data.len() < 2000);
+}
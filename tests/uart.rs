@@ -0,0 +1,54 @@
+//! The serial modes against the emulator, which has nothing attached to
+//! them: writes are discarded and nothing is received.
+
+use std::time::{Duration, Instant};
+
+use buspirate_hal::emulator::Emulator;
+use buspirate_hal::{BusPirate, HdUartConfig, UartConfig};
+
+#[test]
+fn uart_write_is_limited_to_one_request() {
+    let mut bp = BusPirate::from_transport(Emulator::new())
+        .unwrap()
+        .enter_uart_mode(UartConfig::builder().baud_rate(115_200).build(), None)
+        .unwrap();
+
+    let data = [0x55; 2000];
+    let written = embedded_io::Write::write(&mut bp, &data).unwrap();
+    assert!(written > 0 && written < data.len());
+    embedded_io::Write::write_all(&mut bp, &data).unwrap();
+}
+
+#[test]
+fn read_into_a_large_buffer() {
+    let config = HdUartConfig::builder()
+        .baud_rate(115_200)
+        .timeout(Duration::from_millis(10))
+        .build();
+    let mut bp = BusPirate::from_transport(Emulator::new())
+        .unwrap()
+        .enter_hduart_mode(config, None)
+        .unwrap();
+
+    // Larger than one request, and than a u16 length.
+    let mut buf = vec![0u8; 70_000];
+    assert_eq!(bp.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn hduart_transaction_waits_for_the_turnaround() {
+    let config = HdUartConfig::builder()
+        .baud_rate(115_200)
+        .turnaround(Duration::from_millis(20))
+        .timeout(Duration::from_millis(10))
+        .build();
+    let mut bp = BusPirate::from_transport(Emulator::new())
+        .unwrap()
+        .enter_hduart_mode(config, None)
+        .unwrap();
+
+    let started = Instant::now();
+    let mut buf = [0u8; 4];
+    assert_eq!(bp.transaction(&[0x55; 600], &mut buf).unwrap(), 0);
+    assert!(started.elapsed() >= Duration::from_millis(30));
+}